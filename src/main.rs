@@ -1,51 +1,204 @@
-use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use actix_cors::Cors;
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, Compress, Condition, Next};
+use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder, ResponseError};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use parking_lot::RwLock as PLRwLock;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{DateTime, Utc};
+use parking_lot::{Mutex as PLMutex, RwLock as PLRwLock};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use tracing_actix_web::{RequestId, TracingLogger};
 
-const FLARE_API: &str = "https://flare-systems-explorer.flare.network/backend-url/api/v0";
-const CACHE_TTL_SECS: u64 = 300; // 5 minutes
+const DEFAULT_FLARE_API: &str = "https://flare-systems-explorer.flare.network/backend-url/api/v0";
+const DEFAULT_COSTON_API: &str = "https://coston-systems-explorer.flare.rocks/backend-url/api/v0";
+const DEFAULT_COSTON2_API: &str = "https://coston2-systems-explorer.flare.rocks/backend-url/api/v0";
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0";
+const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_CACHE_TTL_SECS: u64 = 300; // 5 minutes
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const RETRY_BASE_DELAY_MS: u64 = 500;
+const RETRY_MAX_DELAY_MS: u64 = 10_000;
+const DEFAULT_UPSTREAM_PAGE_SIZE: usize = 200;
+const MAX_UPSTREAM_PAGE_SIZE: usize = 1_000; // largest /entity page we ask the explorer for
+const MAX_UPSTREAM_PAGES: usize = 50; // guards against an upstream that ignores offset
+const UPSTREAM_CONCURRENCY: usize = 4; // pages requested at once
+const HISTORY_SIZE: usize = 288; // 24 hours of snapshots at the default TTL
+const UPSTREAM_PING_TIMEOUT_SECS: u64 = 3;
+const UPSTREAM_PING_MIN_INTERVAL_SECS: u64 = 5;
+const DEEP_HEALTH_TIMEOUT_SECS: u64 = 2;
+const MAX_NODE_STATUS_BATCH: usize = 500;
+const MAX_COMPARE_IDS: usize = 25;
+const MAX_BATCH_LOOKUP: usize = 500; // ids plus node ids per /api/validators/batch request
+const MAX_DRIFT_HOLDINGS: usize = 500; // positions per /api/select/drift request
+const MAX_ENTITY_CACHE: usize = 1_000; // single-entity lookups kept between refreshes
+const MIN_SEARCH_QUERY_LEN: usize = 2;
+const DEFAULT_RATE_LIMIT_PER_MIN: u32 = 120;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+const MAX_RATE_LIMIT_BUCKETS: usize = 10_000; // distinct client IPs tracked at once
+const SUBSCRIBER_BUFFER: usize = 16; // refresh events an SSE subscriber may fall behind by
+const SSE_KEEPALIVE_SECS: u64 = 15;
+const DEFAULT_SSE_MAX_SUBSCRIBERS: usize = 100;
+const MISSING_FIELD_WARN_SHARE: f64 = 0.5; // warn when more entities than this lack a key field
+const OUTLIER_IQR_FACTOR: f64 = 3.0; // Tukey's fence for "far out" values
+const MIN_OUTLIER_SAMPLE: usize = 4; // fewer rates give no meaningful quartiles
+const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_REQUEST_DEADLINE_MS: u64 = 8_000; // under typical 10s client timeouts
+const DEFAULT_HTTP_CONNECT_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_BODY_TIMEOUT_SECS: u64 = 8;
+const DEFAULT_HTTP_POOL_MAX_IDLE: usize = 2 * UPSTREAM_CONCURRENCY; // a batch of pages plus the single-entity lookups
+const DEFAULT_HTTP_POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+const DEFAULT_HTTP_TCP_KEEPALIVE_SECS: u64 = 60;
+const DEFAULT_VALIDATOR_METRICS_LIMIT: usize = 50;
+const DEFAULT_REWARD_AVG_WINDOW: usize = 12; // one hour at the default TTL
+const DEFAULT_CACHE_FILE: &str = "/tmp/sflr-cache.json";
+const DEFAULT_MAX_WEIGHT_SHARE: f64 = 0.1;
+const DEFAULT_AMOUNT_PRECISION: u32 = 2; // decimal places of suggested stake amounts
+const MAX_AMOUNT_PRECISION: u32 = 9;
+const DELEGATION_BIPS: u64 = 10_000; // WNat delegation shares are basis points of the balance
+const WEI_DECIMALS: u32 = 18;
+const DEFAULT_TOP_LIMIT: usize = 50;
+const DEFAULT_PAGE_LIMIT: usize = 100;
+const MAX_PAGE_LIMIT: usize = 500;
+const DEFAULT_EPOCHS_PER_YEAR: f64 = 365.25 / 3.5; // Flare reward epochs last 3.5 days
+const DEFAULT_AVAILABILITY_SCALE: f64 = 100.0; // Flare reports availability as a percentage
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct ProviderStats {
+    #[schema(required = true)]
     primary: Option<u32>,
+    #[schema(required = true)]
     secondary: Option<u32>,
+    #[schema(required = true)]
     availability: Option<f64>,
+    #[schema(required = true)]
     active: Option<bool>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct Conditions {
     ftso_anchor_feeds: bool,
     ftso_block_latency_feeds: bool,
     fdc: bool,
     staking: bool,
     passes: u8,
+    // Raw upstream value, None when Flare did not report passes at all
+    #[schema(required = true)]
+    passes_held: Option<u8>,
     eligible_for_reward: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct RewardRates {
     wnat: f64,
     mirror: f64,
     pure: f64,
     combined: f64,
+    // An estimate, not a promise: annualize() of the current combined rate,
+    // assuming it holds for EPOCHS_PER_YEAR epochs and rewards are restaked
+    // every epoch
+    apy: f64,
+    // Trailing mean of combined over REWARD_AVG_WINDOW refreshes, or the
+    // instantaneous value (with the flag set) when history is too short
+    combined_avg: f64,
+    combined_avg_fallback: bool,
+    // Sum of wnat, mirror and pure each min-max scaled to 0..1 over the eligible
+    // set, so 0..3; eligible nodes only and only comparable within one snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    normalized_combined: Option<f64>,
+    // Caches written before units were reported load with the defaults
+    #[serde(default)]
+    #[schema(required = true)]
+    units: RateUnits,
+}
+
+// What the numbers in RewardRates measure. Upstream sends bare per-epoch
+// fractions (0.0006 is 0.06%), and every rate here keeps that scale.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+struct RateUnits {
+    // "fraction" of the delegated amount, never a percentage
+    scale: String,
+    // Period covered by wnat, mirror, pure, combined and combined_avg
+    period: String,
+    // Period covered by apy, compounded every epoch
+    apy_period: String,
+    epochs_per_year: f64,
+    // Rewards are paid in the wrapped native token (WFLR on Flare) per unit
+    // of it delegated
+    denomination: String,
+}
+
+impl RateUnits {
+    fn new(epochs_per_year: f64) -> Self {
+        RateUnits {
+            scale: "fraction".to_string(),
+            period: "reward_epoch".to_string(),
+            apy_period: "year".to_string(),
+            epochs_per_year,
+            denomination: "wnat".to_string(),
+        }
+    }
+}
+
+impl Default for RateUnits {
+    fn default() -> Self {
+        RateUnits::new(DEFAULT_EPOCHS_PER_YEAR)
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct Validator {
     id: u32,
     name: String,
+    // First entry of node_ids, kept for existing clients
+    #[schema(required = true)]
     node_id: Option<String>,
+    #[serde(default)]
+    #[schema(required = true)]
+    node_ids: Vec<String>,
+    #[schema(required = true)]
     delegation_address: Option<String>,
+    #[schema(required = true)]
     conditions: Option<Conditions>,
+    #[schema(required = true)]
     provider_stats: Option<ProviderStats>,
+    #[schema(required = true)]
     reward_rates: Option<RewardRates>,
+    // An estimate, not a promise: annualize() of the current combined per-epoch
+    // rate, i.e. (1 + combined) ^ EPOCHS_PER_YEAR - 1. It assumes that rate
+    // holds for a whole year and rewards are restaked every epoch. None
+    // without reward rates.
+    #[serde(default)]
+    #[schema(required = true)]
+    estimated_apy: Option<f64>,
+    // Signing participation counts from upstream's denormalizedentity
+    #[serde(default)]
+    #[schema(required = true)]
+    rewards_signed: Option<u32>,
+    #[serde(default)]
+    #[schema(required = true)]
+    uptime_signed: Option<u32>,
+    // Combined rate as a multiple of the eligible set's median, eligible nodes only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rate_vs_median: Option<f64>,
+    // How many criteria an ineligible node fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unmet_criteria: Option<usize>,
+    // Which criteria an ineligible node fails, only on /ineligible?with_reasons=true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ineligibility_reasons: Option<Vec<String>>,
+    // Other entity ids folded into this one with DEDUP_ENTITIES=true
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    merged_ids: Vec<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct ValidatorResponse {
     timestamp: String,
     total_validators: usize,
@@ -53,37 +206,692 @@ struct ValidatorResponse {
     ineligible_count: usize,
     eligible_nodes: Vec<Validator>,
     ineligible_nodes: Vec<Validator>,
+    // Flare reward epoch the data belongs to, None when upstream did not say;
+    // caches written before this field was added load as None
+    #[serde(default)]
+    #[schema(required = true)]
+    reward_epoch: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct EpochResponse {
+    #[schema(required = true)]
+    reward_epoch: Option<u64>,
+    // When the data the epoch was read from was fetched from upstream
+    last_refresh: String,
+    #[schema(required = true)]
+    age_secs: Option<u64>,
+    timestamp: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Adds data_timestamp (when the underlying data was fetched, which `timestamp`
+// also reports) and served_timestamp (when this response was produced) to a
+// data-bearing response
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct Timestamped<T> {
+    #[serde(flatten)]
+    body: T,
+    data_timestamp: String,
+    served_timestamp: String,
+}
+
+fn timestamped<T>(data_timestamp: &str, body: T) -> Timestamped<T> {
+    Timestamped {
+        body,
+        data_timestamp: data_timestamp.to_string(),
+        served_timestamp: chrono::Utc::now().to_rfc3339(),
+    }
+}
+
+// Published on /api/validators/subscribe after every successful refresh
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct RefreshEvent {
+    timestamp: String,
+    total_validators: usize,
+    eligible_count: usize,
+    ineligible_count: usize,
+}
+
+impl From<&ValidatorResponse> for RefreshEvent {
+    fn from(data: &ValidatorResponse) -> Self {
+        RefreshEvent {
+            timestamp: data.timestamp.clone(),
+            total_validators: data.total_validators,
+            eligible_count: data.eligible_count,
+            ineligible_count: data.ineligible_count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct HealthResponse {
     status: String,
     timestamp: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct CacheStatusResponse {
+    populated: bool,
+    // When the cached data was fetched from upstream (or restored from disk)
+    #[schema(required = true)]
+    last_refresh: Option<String>,
+    #[schema(required = true)]
+    age_secs: Option<u64>,
+    ttl_secs: u64,
+    expired: bool,
+    refresh_in_flight: bool,
+    last_refresh_failed: bool,
+    // From the latest upstream fetch, None until one has completed
+    #[schema(required = true)]
+    field_coverage: Option<FieldCoverage>,
+    timestamp: String,
+    // last_refresh and timestamp under the names the data endpoints use
+    #[schema(required = true)]
+    data_timestamp: Option<String>,
+    served_timestamp: String,
+}
+
+// How many entities of the latest fetch lacked each key field; a sudden jump
+// usually means upstream renamed or moved it
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+struct FieldCoverage {
+    entities: usize,
+    missing_conditions: usize,
+    missing_rewards: usize,
+    missing_provider_stats: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct ValidatorsListResponse {
     timestamp: String,
     count: usize,
     validators: Vec<Validator>,
+    // Only present when the list was cut short by ELIGIBLE_LIST_CAP
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_available: Option<usize>,
+}
+
+// Page position reported by the list endpoints; the response envelope moves
+// it into meta.pagination
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct Pagination {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    has_more: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+struct Paginated<T> {
+    #[serde(flatten)]
+    body: T,
+    pagination: Pagination,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct RefreshResponse {
     success: bool,
     message: String,
     timestamp: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 struct UsageResponse {
     api_name: String,
     version: String,
+    network: String,
+    // Machine-readable description of every endpoint below
+    openapi: String,
     endpoints: Vec<String>,
     timestamp: String,
 }
 
-// Raw data structures from Flare API
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct RankChange {
+    id: u32,
+    from_rank: usize,
+    to_rank: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct RateSample {
+    timestamp: String,
+    #[schema(required = true)]
+    rank: Option<usize>,
+    #[schema(required = true)]
+    wnat: Option<f64>,
+    #[schema(required = true)]
+    mirror: Option<f64>,
+    #[schema(required = true)]
+    pure: Option<f64>,
+    #[schema(required = true)]
+    combined: Option<f64>,
+    // Change in combined since this validator's previous sample
+    #[schema(required = true)]
+    combined_delta: Option<f64>,
+    // Set when the validator was missing from one or more refreshes before this sample
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    gap_before: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct RateHistoryResponse {
+    id: u32,
+    count: usize,
+    samples: Vec<RateSample>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DiffRangeResponse {
+    from: String,
+    to: String,
+    became_eligible: Vec<u32>,
+    became_ineligible: Vec<u32>,
+    rank_changes: Vec<RankChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct ChangedValidator {
+    #[serde(flatten)]
+    validator: Validator,
+    eligible: bool,
+    // Which of combined, eligible and active differ from the baseline
+    changed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct ChangesResponse {
+    since: String,
+    // The snapshot compared against: the latest one taken at or before since
+    #[schema(required = true)]
+    baseline: Option<String>,
+    // Fetch time of the current data
+    timestamp: String,
+    // Set when since predates the retained snapshots; the lists are then empty
+    // and the client should reload the full list instead
+    full_refetch_required: bool,
+    #[schema(required = true)]
+    available_from: Option<String>,
+    changed: Vec<ChangedValidator>,
+    added: Vec<u32>,
+    removed: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct EnvelopeMeta {
+    // When this response was produced
+    timestamp: String,
+    // When the data in it was fetched from upstream
+    #[schema(required = true)]
+    data_timestamp: Option<String>,
+    #[schema(required = true)]
+    cache_age_secs: Option<u64>,
+    // Same values as the X-Cache-Status header
+    #[schema(required = true)]
+    cache_status: Option<String>,
+    #[schema(required = true)]
+    pagination: Option<Pagination>,
+}
+
+// Wrapped response shape: typed on the /api/v2 list routes, and a JSON value
+// when response_envelope() wraps the flat shape of any other route
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct ApiEnvelope<T = serde_json::Value> {
+    #[schema(required = true)]
+    data: Option<T>,
+    meta: EnvelopeMeta,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct UpstreamProbe {
+    reachable: bool,
+    #[schema(required = true)]
+    status_code: Option<u16>,
+    latency_ms: u64,
+    #[schema(required = true)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct UpstreamPingResponse {
+    #[serde(flatten)]
+    probe: UpstreamProbe,
+    timestamp: String,
+}
+
+// Each subsystem is "ok" or "degraded"; the overall status is degraded when any is
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DeepHealthResponse {
+    status: String,
+    upstream: UpstreamHealth,
+    cache: CacheHealth,
+    // Informational, never degrades the status
+    subscribers: SubscriberHealth,
+    timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct UpstreamHealth {
+    status: String,
+    #[serde(flatten)]
+    probe: UpstreamProbe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct CacheHealth {
+    status: String,
+    populated: bool,
+    #[schema(required = true)]
+    age_secs: Option<u64>,
+    expired: bool,
+    last_refresh_failed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SubscriberHealth {
+    open: usize,
+    // SSE_MAX_SUBSCRIBERS, null when unlimited
+    #[schema(required = true)]
+    max: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SelectedValidator {
+    #[serde(flatten)]
+    validator: Validator,
+    weight: f64,
+    // Weight after the diversified strategy's cap, absent otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    effective_weight: Option<f64>,
+    // Chance of being picked on a single draw: weight (or effective_weight) / total_weight,
+    // or 1 / pool_size for a uniform /api/validators/random pick
+    probability: f64,
+    // Share of ?total_amount= suggested for this validator, see allocate_units()
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    amount: Option<f64>,
+    // Score from ?w_reward=/?w_availability=/?w_uptime=, which is also the
+    // weight; absent under the default reward x availability weighting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    composite_score: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SelectionResponse {
+    timestamp: String,
+    strategy: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_weight_share: Option<f64>,
+    // The ?w_*= weights after normalizing, absent when none were given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score_weights: Option<ScoreWeights>,
+    seed: u64,
+    count: usize,
+    total_weight: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_amount: Option<f64>,
+    // Eligible validators left out because their provider is not known to be
+    // active, 0 with ?include_inactive=true
+    #[serde(default)]
+    #[schema(required = true)]
+    excluded_inactive: usize,
+    // Validators left to weight after the filters, address lists and the
+    // inactive rule
+    #[serde(default)]
+    #[schema(required = true)]
+    candidate_pool: usize,
+    // Reward-rate outliers kept out of the pool with ?exclude_outliers=true
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    excluded_outliers: Vec<u32>,
+    validators: Vec<SelectedValidator>,
+}
+
+// ?format=tx on /api/validators/select: the picks as the arguments of
+// WNat.batchDelegate(address[] delegatees, uint256[] bips), entry i of every
+// list belonging to the same validator
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DelegationTx {
+    seed: u64,
+    method: String,
+    // Which validator each entry is for, not part of the call
+    ids: Vec<u32>,
+    delegatees: Vec<String>,
+    // Shares of the delegated balance in basis points, summing to exactly 10000
+    bips: Vec<u64>,
+    // With ?total_amount=, each share in wei (10^-18) as a decimal string,
+    // since wei amounts overflow JSON numbers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    amounts_wei: Option<Vec<String>>,
+}
+
+// What /api/validators/select answers with, depending on ?format=
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(untagged)]
+enum SelectionBody {
+    Selection(Timestamped<SelectionResponse>),
+    Tx(Timestamped<DelegationTx>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct RandomPickResponse {
+    timestamp: String,
+    seed: u64,
+    weighted: bool,
+    // Eligible validators the pick was drawn from
+    pool_size: usize,
+    validator: SelectedValidator,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct ComparedValidator {
+    #[serde(flatten)]
+    validator: Validator,
+    eligible: bool,
+    // 1-based position in the eligible set by combined rate, None when ineligible
+    #[schema(required = true)]
+    rank: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct CompareMissing {
+    id: u32,
+    error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct CompareResponse {
+    timestamp: String,
+    eligible_count: usize,
+    count: usize,
+    validators: Vec<ComparedValidator>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    not_found: Vec<CompareMissing>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SearchMatch {
+    #[serde(flatten)]
+    validator: Validator,
+    eligible: bool,
+    // 1-based position in the eligible set by combined rate, None when ineligible
+    #[schema(required = true)]
+    rank: Option<usize>,
+    // Edits between the query and the closest part of the name, fuzzy searches only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    distance: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SearchResponse {
+    timestamp: String,
+    query: String,
+    fuzzy: bool,
+    count: usize,
+    matches: Vec<SearchMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DelegationUrlResponse {
+    id: u32,
+    name: String,
+    delegation_address: String,
+    // Filled from DELEGATION_URL_TEMPLATE when configured
+    #[schema(required = true)]
+    url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct NodeStatusRequest {
+    node_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct NodeStatus {
+    node_id: String,
+    found: bool,
+    #[schema(required = true)]
+    validator_id: Option<u32>,
+    #[schema(required = true)]
+    name: Option<String>,
+    #[schema(required = true)]
+    eligible: Option<bool>,
+    #[schema(required = true)]
+    conditions: Option<Conditions>,
+    #[schema(required = true)]
+    reward_rates: Option<RewardRates>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct NodeStatusResponse {
+    timestamp: String,
+    nodes: Vec<NodeStatus>,
+    unknown_node_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct BatchLookupRequest {
+    #[serde(default)]
+    ids: Vec<u32>,
+    #[serde(default)]
+    node_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct BatchLookupResponse {
+    timestamp: String,
+    count: usize,
+    // Ids first, then node ids, in request order; each validator appears once
+    // even when several of its keys were asked for
+    validators: Vec<ComparedValidator>,
+    not_found_ids: Vec<u32>,
+    not_found_node_ids: Vec<String>,
+}
+
+// One position of an existing delegation split posted to /api/select/drift
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+struct DriftHolding {
+    id: u32,
+    amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DriftResponse {
+    timestamp: String,
+    // Sum of the held amounts, which the recommendation splits anew
+    total_amount: f64,
+    // Stake that has to move to reach the recommendation, half the summed
+    // absolute differences
+    drift_amount: f64,
+    // drift_amount / total_amount: 0 on target, 1 when nothing held is recommended
+    drift_share: f64,
+    // Held ids that are ineligible now, and ids the cached data doesn't know
+    now_ineligible: Vec<u32>,
+    unknown_ids: Vec<u32>,
+    // Held validators in request order, then the recommended ones not held
+    positions: Vec<DriftPosition>,
+    moves: Vec<RebalanceMove>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DriftPosition {
+    id: u32,
+    #[schema(required = true)]
+    name: Option<String>,
+    // eligible, ineligible or unknown
+    status: String,
+    current: f64,
+    recommended: f64,
+    // recommended - current: positive is under-allocated, negative over
+    difference: f64,
+}
+
+// Move `amount` of stake from validator `from` to validator `to`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct RebalanceMove {
+    from: u32,
+    to: u32,
+    amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SchemaReport {
+    checked_at: String,
+    // Fields our structs read that no entity carried
+    missing_expected: Vec<String>,
+    // Fields upstream sends that we do not read
+    unknown_fields: Vec<String>,
+    // Changes relative to the previous fetch
+    appeared: Vec<String>,
+    vanished: Vec<String>,
+    observed_fields: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct DiagnosticsResponse {
+    schema_diagnostics: bool,
+    #[schema(required = true)]
+    schema: Option<SchemaReport>,
+    timestamp: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct SelfTestResponse {
+    passed: bool,
+    violations: Vec<String>,
+    timestamp: String,
+}
+
+// Summary of one metric over the eligible set, null when the set is empty
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+struct SummaryStats {
+    count: usize,
+    #[schema(required = true)]
+    mean: Option<f64>,
+    #[schema(required = true)]
+    median: Option<f64>,
+    #[schema(required = true)]
+    min: Option<f64>,
+    #[schema(required = true)]
+    max: Option<f64>,
+    #[schema(required = true)]
+    stddev: Option<f64>,
+}
+
+// How many validators (eligible and ineligible) pass each condition
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+struct ConditionCounts {
+    ftso_anchor_feeds: usize,
+    ftso_block_latency_feeds: usize,
+    fdc: usize,
+    staking: usize,
+    eligible_for_reward: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+struct StatsResponse {
+    timestamp: String,
+    total_validators: usize,
+    eligible_count: usize,
+    combined_rate: SummaryStats,
+    availability: SummaryStats,
+    condition_counts: ConditionCounts,
+    // Validators by passes held, keyed "0" through "4"
+    passes_distribution: std::collections::BTreeMap<u8, usize>,
+    // Eligible validators left out of combined_rate and availability, only
+    // with ?exclude_outliers=true
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    excluded_outliers: Vec<u32>,
+}
+
+// Compact per-refresh record kept for history lookups
+#[derive(Debug, Clone)]
+struct SnapshotEntry {
+    id: u32,
+    // 1-based position in the eligible list, None when ineligible
+    rank: Option<usize>,
+    wnat: Option<f64>,
+    mirror: Option<f64>,
+    pure: Option<f64>,
+    combined: Option<f64>,
+    active: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    timestamp: DateTime<Utc>,
+    entries: Vec<SnapshotEntry>,
+}
+
+// Which network's explorer to read, from NETWORK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Network {
+    Flare,
+    Coston,
+    Coston2,
+}
+
+impl std::str::FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "flare" => Ok(Network::Flare),
+            "coston" => Ok(Network::Coston),
+            "coston2" => Ok(Network::Coston2),
+            _ => Err(format!("unknown NETWORK {:?}, expected one of: flare, coston, coston2", s)),
+        }
+    }
+}
+
+impl Network {
+    fn name(self) -> &'static str {
+        match self {
+            Network::Flare => "flare",
+            Network::Coston => "coston",
+            Network::Coston2 => "coston2",
+        }
+    }
+
+    // FLARE_API_URL still takes precedence
+    fn default_api_url(self) -> &'static str {
+        match self {
+            Network::Flare => DEFAULT_FLARE_API,
+            Network::Coston => DEFAULT_COSTON_API,
+            Network::Coston2 => DEFAULT_COSTON2_API,
+        }
+    }
+
+    // Condition fields this network's explorer publishes under another name,
+    // as (upstream name, name FlareEntityMinConditions reads)
+    fn condition_aliases(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Network::Flare => &[],
+            Network::Coston | Network::Coston2 => &[
+                ("ftso_anchor_feeds", "ftso_scaling"),
+                ("ftso_block_latency_feeds", "ftso_fast_updates"),
+            ],
+        }
+    }
+}
+
+// Renames one raw entity's conditions to the names our structs read
+fn apply_condition_aliases(entity: &mut serde_json::Value, network: Network) {
+    let Some(conditions) = entity.get_mut("entityminimalconditions").and_then(|c| c.as_object_mut()) else {
+        return;
+    };
+    for (upstream, ours) in network.condition_aliases() {
+        if let Some(value) = conditions.remove(*upstream) {
+            conditions.entry(ours.to_string()).or_insert(value);
+        }
+    }
+}
+
+// Raw data structures from Flare API. Everything but the entity id is optional
+// because the testnet explorers omit whole sections (providersuccessrate in
+// particular). Their differing field names are renamed per network before
+// deserializing, see Network::condition_aliases.
 #[derive(Debug, Deserialize)]
 struct FlareEntityMinConditions {
     ftso_scaling: Option<bool>,
@@ -92,6 +900,7 @@ struct FlareEntityMinConditions {
     staking: Option<bool>,
     passes_held: Option<u8>,
     eligible_for_reward: Option<bool>,
+    reward_epoch: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -99,13 +908,15 @@ struct FlareRewards {
     reward_rate_wnat: Option<f64>,
     reward_rate_mirror: Option<f64>,
     reward_rate_pure: Option<f64>,
+    reward_epoch: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct FlareProviderSuccessRate {
     primary: Option<u32>,
     secondary: Option<u32>,
-    availability: Option<u32>,
+    // Scale given by AVAILABILITY_SCALE; f64 so a fractional value still parses
+    availability: Option<f64>,
     active: Option<bool>,
 }
 
@@ -113,6 +924,7 @@ struct FlareProviderSuccessRate {
 #[allow(dead_code)]
 struct FlareDenormalizedEntity {
     id: Option<u32>,
+    #[serde(default)]
     node_ids: Vec<String>,
     public_key: Option<String>,
     submit_signatures_address: Option<String>,
@@ -141,273 +953,5900 @@ struct FlareEntity {
 
 #[derive(Debug, Deserialize)]
 struct FlareEntityList {
+    // Total across all pages, when upstream reports it
+    count: Option<usize>,
     results: Vec<FlareEntity>,
 }
 
-struct AppState {
-    http_client: Client,
-    cache: PLRwLock<Option<(ValidatorResponse, SystemTime)>>,
+// Upper bounds (seconds) of the upstream fetch duration histogram buckets
+const FETCH_DURATION_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// Hand-rolled Prometheus registry, rendered by /metrics
+#[derive(Default)]
+struct Metrics {
+    // Keyed by (method, route pattern)
+    requests: PLMutex<HashMap<(String, String), u64>>,
+    cache_hits: AtomicU64,
+    cache_stale: AtomicU64,
+    cache_stale_error: AtomicU64,
+    cache_misses: AtomicU64,
+    upstream_successes: AtomicU64,
+    upstream_failures: AtomicU64,
+    // Cumulative counts per FETCH_DURATION_BUCKETS entry
+    fetch_duration_buckets: [AtomicU64; FETCH_DURATION_BUCKETS.len()],
+    fetch_duration_sum_micros: AtomicU64,
+    fetch_duration_count: AtomicU64,
+    // From the last successful fetch
+    eligible_validators: AtomicU64,
+    ineligible_validators: AtomicU64,
 }
 
-async fn fetch_validator_data(state: &AppState) -> Result<ValidatorResponse, reqwest::Error> {
-    // First check cache
-    {
-        let cache_read = state.cache.read();
-        if let Some((data, timestamp)) = &*cache_read {
-            let elapsed = SystemTime::now().duration_since(*timestamp).unwrap_or(Duration::from_secs(CACHE_TTL_SECS + 1));
-            if elapsed < Duration::from_secs(CACHE_TTL_SECS) {
-                return Ok(data.clone());
+impl Metrics {
+    fn record_request(&self, method: &str, endpoint: &str) {
+        *self.requests.lock().entry((method.to_string(), endpoint.to_string())).or_default() += 1;
+    }
+
+    fn record_cache(&self, status: CacheStatus) {
+        let counter = match status {
+            CacheStatus::Hit => &self.cache_hits,
+            CacheStatus::Stale => &self.cache_stale,
+            CacheStatus::StaleError => &self.cache_stale_error,
+            CacheStatus::Miss => &self.cache_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_fetch(&self, duration: Duration, success: bool) {
+        let counter = if success { &self.upstream_successes } else { &self.upstream_failures };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let secs = duration.as_secs_f64();
+        for (bucket, bound) in self.fetch_duration_buckets.iter().zip(FETCH_DURATION_BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
             }
         }
+        self.fetch_duration_sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.fetch_duration_count.fetch_add(1, Ordering::Relaxed);
     }
 
-    // Cache miss or expired, fetch fresh data
-    let url = format!("{}/entity?limit=200&offset=0", FLARE_API);
-    let response = state.http_client.get(&url).send().await?;
-    let entity_list: FlareEntityList = response.json().await?;
-
-    let mut eligible_nodes = Vec::new();
-    let mut ineligible_nodes = Vec::new();
-
-    for entity in &entity_list.results {
-        let validator = process_entity(entity);
-
-        // Check eligibility based on our strict criteria
-        if let Some(cond) = &validator.conditions {
-            if cond.eligible_for_reward &&
-               cond.ftso_anchor_feeds &&
-               cond.ftso_block_latency_feeds &&
-               cond.fdc &&
-               cond.staking &&
-               cond.passes == 3 {
-                eligible_nodes.push(validator);
-            } else {
-                ineligible_nodes.push(validator);
-            }
-        } else {
-            ineligible_nodes.push(validator);
+    fn render(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP flare_api_requests_total HTTP requests handled, by endpoint");
+        let _ = writeln!(out, "# TYPE flare_api_requests_total counter");
+        let mut requests: Vec<_> = self.requests.lock().iter().map(|(k, v)| (k.clone(), *v)).collect();
+        requests.sort();
+        for ((method, endpoint), count) in requests {
+            let _ = writeln!(
+                out,
+                "flare_api_requests_total{{method=\"{}\",endpoint=\"{}\"}} {}",
+                escape_label(&method), escape_label(&endpoint), count
+            );
+        }
+
+        let _ = writeln!(out, "# HELP flare_api_cache_requests_total Cache lookups, by result");
+        let _ = writeln!(out, "# TYPE flare_api_cache_requests_total counter");
+        for (result, counter) in [
+            ("hit", &self.cache_hits),
+            ("stale", &self.cache_stale),
+            ("stale-error", &self.cache_stale_error),
+            ("miss", &self.cache_misses),
+        ] {
+            let _ = writeln!(out, "flare_api_cache_requests_total{{result=\"{}\"}} {}", result, counter.load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(out, "# HELP flare_api_upstream_fetches_total Upstream refreshes, by outcome");
+        let _ = writeln!(out, "# TYPE flare_api_upstream_fetches_total counter");
+        for (outcome, counter) in [("success", &self.upstream_successes), ("failure", &self.upstream_failures)] {
+            let _ = writeln!(out, "flare_api_upstream_fetches_total{{outcome=\"{}\"}} {}", outcome, counter.load(Ordering::Relaxed));
         }
+
+        let _ = writeln!(out, "# HELP flare_api_upstream_fetch_duration_seconds Duration of upstream refreshes");
+        let _ = writeln!(out, "# TYPE flare_api_upstream_fetch_duration_seconds histogram");
+        for (bucket, bound) in self.fetch_duration_buckets.iter().zip(FETCH_DURATION_BUCKETS) {
+            let _ = writeln!(out, "flare_api_upstream_fetch_duration_seconds_bucket{{le=\"{}\"}} {}", bound, bucket.load(Ordering::Relaxed));
+        }
+        let count = self.fetch_duration_count.load(Ordering::Relaxed);
+        let sum = self.fetch_duration_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "flare_api_upstream_fetch_duration_seconds_bucket{{le=\"+Inf\"}} {}", count);
+        let _ = writeln!(out, "flare_api_upstream_fetch_duration_seconds_sum {}", sum);
+        let _ = writeln!(out, "flare_api_upstream_fetch_duration_seconds_count {}", count);
+
+        let _ = writeln!(out, "# HELP flare_api_eligible_validators Eligible validators in the last successful fetch");
+        let _ = writeln!(out, "# TYPE flare_api_eligible_validators gauge");
+        let _ = writeln!(out, "flare_api_eligible_validators {}", self.eligible_validators.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP flare_api_ineligible_validators Ineligible validators in the last successful fetch");
+        let _ = writeln!(out, "# TYPE flare_api_ineligible_validators gauge");
+        let _ = writeln!(out, "flare_api_ineligible_validators {}", self.ineligible_validators.load(Ordering::Relaxed));
+
+        out
     }
+}
 
-    // Sort eligible nodes by combined reward rate
-    eligible_nodes.sort_by(|a, b| {
-        let rate_a = a.reward_rates.as_ref().map_or(0.0, |r| r.combined);
-        let rate_b = b.reward_rates.as_ref().map_or(0.0, |r| r.combined);
-        rate_b.partial_cmp(&rate_a).unwrap_or(std::cmp::Ordering::Equal)
-    });
+// What /api/validators/subscribe does when SSE_MAX_SUBSCRIBERS streams are open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SseFullPolicy {
+    // Answer 503 to the new subscriber
+    Reject,
+    // Close the longest-open stream to make room
+    EvictOldest,
+}
 
-    let response = ValidatorResponse {
-        timestamp: chrono::Utc::now().to_rfc3339(),
-        total_validators: entity_list.results.len(),
-        eligible_count: eligible_nodes.len(),
-        ineligible_count: ineligible_nodes.len(),
-        eligible_nodes,
-        ineligible_nodes,
-    };
+impl std::str::FromStr for SseFullPolicy {
+    type Err = String;
 
-    // Update cache
-    {
-        let mut cache_write = state.cache.write();
-        *cache_write = Some((response.clone(), SystemTime::now()));
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(SseFullPolicy::Reject),
+            "evict_oldest" => Ok(SseFullPolicy::EvictOldest),
+            _ => Err(format!("unknown SSE_FULL_POLICY {:?}, expected reject or evict_oldest", s)),
+        }
     }
+}
 
-    Ok(response)
+// Open /api/validators/subscribe streams, oldest first. Each stream holds an
+// SseSubscription that takes it off the list when the stream is dropped.
+struct SseSubscribers {
+    max: Option<usize>,
+    policy: SseFullPolicy,
+    next_id: AtomicU64,
+    // Notified to close a stream evicted to make room
+    open: PLMutex<VecDeque<(u64, Arc<tokio::sync::Notify>)>>,
+    rejected: AtomicU64,
+    evicted: AtomicU64,
 }
 
-fn process_entity(entity: &FlareEntity) -> Validator {
-    // Extract conditions
-    let conditions = entity.entityminimalconditions.as_ref().map(|c| Conditions {
-        ftso_anchor_feeds: c.ftso_scaling.unwrap_or(false),
-        ftso_block_latency_feeds: c.ftso_fast_updates.unwrap_or(false),
-        fdc: c.fdc.unwrap_or(false),
-        staking: c.staking.unwrap_or(false),
-        passes: c.passes_held.unwrap_or(0),
-        eligible_for_reward: c.eligible_for_reward.unwrap_or(false),
-    });
+struct SseSubscription {
+    subscribers: Arc<SseSubscribers>,
+    id: u64,
+    evicted: Arc<tokio::sync::Notify>,
+}
 
-    // Extract reward rates
-    let reward_rates = entity.rewards.as_ref().map(|r| {
-        let wnat = r.reward_rate_wnat.unwrap_or(0.0);
-        let mirror = r.reward_rate_mirror.unwrap_or(0.0);
-        let pure = r.reward_rate_pure.unwrap_or(0.0);
+impl Drop for SseSubscription {
+    fn drop(&mut self) {
+        self.subscribers.open.lock().retain(|(id, _)| *id != self.id);
+    }
+}
 
-        RewardRates {
-            wnat,
-            mirror,
-            pure,
-            combined: wnat + mirror + pure,
+impl SseSubscribers {
+    fn new(max: Option<usize>, policy: SseFullPolicy) -> Self {
+        SseSubscribers {
+            max,
+            policy,
+            next_id: AtomicU64::new(0),
+            open: PLMutex::new(VecDeque::new()),
+            rejected: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
         }
-    });
+    }
 
-    // Extract provider stats
-    let provider_stats = entity.providersuccessrate.as_ref().map(|p| ProviderStats {
-        primary: p.primary,
-        secondary: p.secondary,
-        availability: p.availability.map(|a| a as f64 / 100.0),
-        active: p.active,
-    });
+    fn count(&self) -> usize {
+        self.open.lock().len()
+    }
 
-    Validator {
+    // None when the cap is reached and the policy is to reject
+    fn admit(self: &Arc<Self>) -> Option<SseSubscription> {
+        let mut open = self.open.lock();
+        if let Some(max) = self.max.filter(|&max| open.len() >= max) {
+            if self.policy == SseFullPolicy::Reject {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+            while open.len() >= max {
+                let Some((_, evicted)) = open.pop_front() else { break };
+                // Stores a permit, so a stream not currently waiting still sees it
+                evicted.notify_one();
+                self.evicted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let evicted = Arc::new(tokio::sync::Notify::new());
+        open.push_back((id, Arc::clone(&evicted)));
+        Some(SseSubscription { subscribers: Arc::clone(self), id, evicted })
+    }
+
+    fn render(&self, out: &mut String) {
+        use std::fmt::Write;
+
+        let _ = writeln!(out, "# HELP flare_api_sse_subscribers Open /api/validators/subscribe streams");
+        let _ = writeln!(out, "# TYPE flare_api_sse_subscribers gauge");
+        let _ = writeln!(out, "flare_api_sse_subscribers {}", self.count());
+        let _ = writeln!(out, "# HELP flare_api_sse_rejected_total Subscribers turned away with 503 at SSE_MAX_SUBSCRIBERS");
+        let _ = writeln!(out, "# TYPE flare_api_sse_rejected_total counter");
+        let _ = writeln!(out, "flare_api_sse_rejected_total {}", self.rejected.load(Ordering::Relaxed));
+        let _ = writeln!(out, "# HELP flare_api_sse_evicted_total Streams closed to make room for a newer subscriber");
+        let _ = writeln!(out, "# TYPE flare_api_sse_evicted_total counter");
+        let _ = writeln!(out, "flare_api_sse_evicted_total {}", self.evicted.load(Ordering::Relaxed));
+    }
+}
+
+// Per-client token buckets: each IP may burst up to `per_min` requests and
+// then gets per_min / 60 more every second
+struct RateLimiter {
+    per_min: f64,
+    buckets: PLMutex<HashMap<std::net::IpAddr, TokenBucket>>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(per_min: u32) -> Self {
+        RateLimiter {
+            per_min: f64::from(per_min),
+            buckets: PLMutex::new(HashMap::new()),
+        }
+    }
+
+    // Takes a token for `ip`, or returns how long until one is available
+    fn check(&self, ip: std::net::IpAddr, now: Instant) -> Result<(), Duration> {
+        let per_sec = self.per_min / 60.0;
+        let refill = |bucket: &TokenBucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * per_sec).min(self.per_min)
+        };
+
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_RATE_LIMIT_BUCKETS && !buckets.contains_key(&ip) {
+            // A full bucket behaves exactly like a new one, so it can go
+            buckets.retain(|_, bucket| refill(bucket) < self.per_min);
+            if buckets.len() >= MAX_RATE_LIMIT_BUCKETS {
+                let oldest = buckets.iter().min_by_key(|(_, b)| b.updated).map(|(ip, _)| *ip);
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(ip).or_insert(TokenBucket { tokens: self.per_min, updated: now });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+// One TRUSTED_PROXIES entry, a CIDR block or a bare address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpNetwork {
+    addr: std::net::IpAddr,
+    prefix: u8,
+}
+
+impl std::str::FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: std::net::IpAddr = addr.parse()
+            .map_err(|_| format!("invalid address in {:?}", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            None => max,
+            Some(p) => match p.parse::<u8>() {
+                Ok(p) if p <= max => p,
+                _ => return Err(format!("invalid prefix length in {:?}: expected 0-{}", s, max)),
+            },
+        };
+        Ok(IpNetwork { addr, prefix })
+    }
+}
+
+impl IpNetwork {
+    fn contains(&self, ip: std::net::IpAddr) -> bool {
+        use std::net::IpAddr;
+        // A dual-stack listener reports IPv4 peers as ::ffff:a.b.c.d
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+// Parses TRUSTED_PROXIES, a comma-separated list of CIDR blocks or addresses
+fn parse_trusted_proxies(raw: &str) -> Result<Vec<IpNetwork>, String> {
+    raw.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| entry.parse::<IpNetwork>().map_err(|e| format!("invalid TRUSTED_PROXIES: {}", e)))
+        .collect()
+}
+
+// Who X-Forwarded-For and X-Forwarded-Proto are believed from. Anyone else
+// can put whatever they like in those headers, so for other peers they are
+// ignored and the socket address is the client.
+#[derive(Debug, Default)]
+struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+    // Deprecated TRUST_FORWARDED_FOR=true: believe one hop from any peer
+    any_peer: bool,
+}
+
+// The client as seen through any trusted proxies in front of us
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClientAddr {
+    ip: Option<std::net::IpAddr>,
+    // X-Forwarded-Proto from a trusted peer, lowercased
+    forwarded_proto: Option<String>,
+}
+
+impl TrustedProxies {
+    fn trusts(&self, ip: std::net::IpAddr) -> bool {
+        self.networks.iter().any(|net| net.contains(ip))
+    }
+
+    fn resolve(&self, peer: Option<std::net::IpAddr>, headers: &actix_web::http::header::HeaderMap) -> ClientAddr {
+        let untrusted = ClientAddr { ip: peer, forwarded_proto: None };
+        let Some(peer) = peer.filter(|&peer| self.any_peer || self.trusts(peer)) else {
+            return untrusted;
+        };
+
+        // Each proxy appends the address it saw, so walk right to left past
+        // our own proxies; the first hop we don't run is the client and
+        // anything left of it is whatever that client claimed
+        let mut client = peer;
+        let hops: Vec<&str> = headers.get_all("X-Forwarded-For")
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .collect();
+        for hop in hops.iter().rev() {
+            let hop = hop.trim();
+            let Some(ip) = hop.parse::<std::net::IpAddr>().ok()
+                .or_else(|| hop.parse::<std::net::SocketAddr>().ok().map(|addr| addr.ip()))
+            else {
+                break;
+            };
+            client = ip;
+            if !self.trusts(ip) {
+                break;
+            }
+        }
+
+        let forwarded_proto = headers.get("X-Forwarded-Proto")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.rsplit(',').next())
+            .map(|proto| proto.trim().to_ascii_lowercase())
+            .filter(|proto| proto == "http" || proto == "https");
+        ClientAddr { ip: Some(client), forwarded_proto }
+    }
+}
+
+fn client_addr(req: &ServiceRequest) -> ClientAddr {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    match req.app_data::<web::Data<Arc<AppState>>>() {
+        Some(state) => state.trusted_proxies.resolve(peer, req.headers()),
+        None => ClientAddr { ip: peer, forwarded_proto: None },
+    }
+}
+
+// TracingLogger's default span takes http.client_ip and http.scheme from
+// forwarded headers whoever sent them; this one goes through TRUSTED_PROXIES
+struct ClientSpan;
+
+impl tracing_actix_web::RootSpanBuilder for ClientSpan {
+    fn on_request_start(request: &ServiceRequest) -> tracing::Span {
+        use actix_web::http::Version;
+        use actix_web::HttpMessage;
+
+        let client = client_addr(request);
+        let forwarded = client.forwarded_proto.is_some();
+        // connection_info() reads forwarded headers itself, so fall back to
+        // the request line and then to the plain HTTP we listen on
+        let scheme = client.forwarded_proto
+            .or_else(|| request.uri().scheme_str().map(str::to_string))
+            .unwrap_or_else(|| "http".to_string());
+        // X-Forwarded-Host is only believed along with X-Forwarded-Proto
+        let host = if forwarded {
+            request.connection_info().host().to_string()
+        } else {
+            request.headers().get(actix_web::http::header::HOST)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let flavor = match request.version() {
+            Version::HTTP_09 => "0.9",
+            Version::HTTP_10 => "1.0",
+            Version::HTTP_11 => "1.1",
+            Version::HTTP_2 => "2.0",
+            Version::HTTP_3 => "3.0",
+            _ => "unknown",
+        };
+        let route = request.match_pattern().unwrap_or_default();
+        let request_id = request.extensions().get::<RequestId>().copied();
+        let user_agent = request.headers().get(actix_web::http::header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default();
+
+        tracing::info_span!(
+            "HTTP request",
+            http.method = %request.method(),
+            http.route = %route,
+            http.flavor = %flavor,
+            http.scheme = %scheme,
+            http.host = %host,
+            http.client_ip = %client.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            http.user_agent = %user_agent,
+            http.target = %request.uri().path_and_query().map(|p| p.as_str()).unwrap_or(""),
+            http.status_code = tracing::field::Empty,
+            otel.name = %format!("{} {}", request.method(), route),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            request_id = %request_id.map(|id| id.to_string()).unwrap_or_default(),
+            exception.message = tracing::field::Empty,
+            exception.details = tracing::field::Empty,
+        )
+    }
+
+    fn on_request_end<B: MessageBody>(span: tracing::Span, outcome: &Result<ServiceResponse<B>, actix_web::Error>) {
+        tracing_actix_web::DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+struct AppState {
+    http_client: Client,
+    network: Network,
+    flare_api: String,
+    cache_ttl: Duration,
+    cache: PLRwLock<Option<CachedData>>,
+    history: PLRwLock<VecDeque<Snapshot>>,
+    epochs_per_year: f64,
+    eligible_list_cap: Option<usize>,
+    last_upstream_ping: PLMutex<Option<Instant>>,
+    wrap_responses: bool,
+    // None disables /metrics/validators, otherwise the max validators exported
+    validator_metrics_limit: Option<usize>,
+    body_timeout: Duration,
+    field_aliases: HashMap<String, String>,
+    schema_diagnostics: bool,
+    schema_report: PLRwLock<Option<SchemaReport>>,
+    field_coverage: PLRwLock<Option<FieldCoverage>>,
+    criteria: EligibilityCriteria,
+    delegation_url_template: Option<String>,
+    reward_avg_window: usize,
+    // Merge entities sharing a delegation address or node id, see dedup_validators()
+    dedup_entities: bool,
+    // Compute derived views such as /api/stats during the refresh rather than
+    // on the first request after it
+    warm_derived_views: bool,
+    refresh_in_flight: AtomicBool,
+    // Woken whenever a refresh finishes, see wait_for_refresh()
+    refresh_finished: tokio::sync::Notify,
+    // Whether the most recent upstream refresh failed
+    last_refresh_failed: AtomicBool,
+    // How that failure is reported to requests waiting on an empty cache
+    last_refresh_error: PLMutex<Option<AppError>>,
+    // Set once the cache has been populated, see /ready
+    ready: AtomicBool,
+    max_retries: u32,
+    // How long a request waits on an upstream fetch when nothing is cached,
+    // None when REQUEST_DEADLINE_MS=0
+    request_deadline: Option<Duration>,
+    // Entities per /entity request, at most MAX_UPSTREAM_PAGE_SIZE
+    upstream_page_size: usize,
+    // None fetches every entity upstream has
+    upstream_max_entities: Option<usize>,
+    metrics: Metrics,
+    // Bearer token required by /api/refresh, open when unset
+    refresh_api_key: Option<String>,
+    // Raw upstream availability that means 100%
+    availability_scale: f64,
+    // None when RATE_LIMIT_PER_MIN=0
+    rate_limiter: Option<RateLimiter>,
+    // Peers whose X-Forwarded-* headers are believed, for logs and rate limiting
+    trusted_proxies: TrustedProxies,
+    // Where the cache is persisted across restarts, None when CACHE_FILE=""
+    cache_file: Option<std::path::PathBuf>,
+    // Fans refresh summaries out to /api/validators/subscribe; sending never
+    // waits on subscribers, a lagging one is dropped instead
+    refresh_events: tokio::sync::broadcast::Sender<RefreshEvent>,
+    // Caps the streams subscribed to refresh_events, see SSE_MAX_SUBSCRIBERS
+    sse_subscribers: Arc<SseSubscribers>,
+    // Validators fetched one at a time by /api/validators/{id} when the full
+    // list lacked them, None where upstream has no such entity; kept apart
+    // from `cache` and cleared by every refresh
+    entity_cache: PLMutex<HashMap<u32, (Option<Validator>, SystemTime)>>,
+}
+
+// One refresh worth of data. Handlers share it through the Arc instead of
+// cloning every validator per request, and anything derived from the whole
+// list is computed here once rather than on each hit.
+struct CachedData {
+    data: Arc<ValidatorResponse>,
+    // /api/stats with and without outliers; filled at refresh when
+    // WARM_DERIVED_VIEWS is on, otherwise by the first request asking
+    stats: std::sync::OnceLock<Arc<StatsResponse>>,
+    stats_without_outliers: std::sync::OnceLock<Arc<StatsResponse>>,
+    // Ids of eligible validators with an implausible combined rate, see rate_outliers()
+    outliers: Vec<u32>,
+    // See content_hash(), the basis of list ETags
+    content_hash: u64,
+    fetched_at: SystemTime,
+}
+
+impl CachedData {
+    fn new(data: ValidatorResponse, fetched_at: SystemTime, warm: bool) -> Self {
+        let outliers = rate_outliers(&data.eligible_nodes);
+        if !outliers.is_empty() {
+            log::warn!(
+                "Flagged {} eligible validators with a combined rate outside the outlier fence: ids {:?}",
+                outliers.len(), outliers
+            );
+        }
+        let cached = CachedData {
+            stats: std::sync::OnceLock::new(),
+            stats_without_outliers: std::sync::OnceLock::new(),
+            outliers,
+            content_hash: content_hash(&data),
+            data: Arc::new(data),
+            fetched_at,
+        };
+        if warm {
+            let started = Instant::now();
+            cached.stats(false);
+            cached.stats(true);
+            log::debug!("Warmed derived views in {}µs", started.elapsed().as_micros());
+        }
+        cached
+    }
+
+    fn stats(&self, exclude_outliers: bool) -> Arc<StatsResponse> {
+        let (slot, excluded) = if exclude_outliers {
+            (&self.stats_without_outliers, self.outliers.as_slice())
+        } else {
+            (&self.stats, &[][..])
+        };
+        Arc::clone(slot.get_or_init(|| Arc::new(compute_stats(&self.data, excluded))))
+    }
+}
+
+// On-disk form of the cache, see save_cache()
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedCache {
+    saved_at: SystemTime,
+    data: ValidatorResponse,
+}
+
+#[derive(Debug)]
+enum FetchError {
+    Http(reqwest::Error),
+    // Connected and got headers, but the body did not arrive in time
+    BodyTimeout(Duration),
+    Decode(serde_json::Error),
+    // A page after the first failed, the entities fetched so far are discarded
+    PartialPage {
+        offset: usize,
+        fetched: usize,
+        source: Box<FetchError>,
+    },
+    // MAX_UPSTREAM_PAGES full pages and still no end, e.g. offset is ignored
+    PageLimit { pages: usize, fetched: usize },
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Http(e) => write!(f, "upstream request failed: {}", e),
+            FetchError::BodyTimeout(after) => {
+                write!(f, "upstream body read stalled after {}s", after.as_secs())
+            },
+            FetchError::Decode(e) => write!(f, "upstream response did not match schema: {}", e),
+            FetchError::PartialPage { offset, fetched, source } => write!(
+                f,
+                "upstream page at offset {} failed after {} entities were fetched: {}",
+                offset, fetched, source
+            ),
+            FetchError::PageLimit { pages, fetched } => write!(
+                f,
+                "upstream still had entities after {} pages ({} fetched), the list would be incomplete",
+                pages, fetched
+            ),
+        }
+    }
+}
+
+impl FetchError {
+    // Connection failures, timeouts and 5xx are worth retrying; 4xx and bodies
+    // that do not parse will fail the same way again
+    fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Http(e) => {
+                e.is_connect() || e.is_timeout() || e.status().is_some_and(|s| s.is_server_error())
+            },
+            FetchError::BodyTimeout(_) => true,
+            FetchError::Decode(_) | FetchError::PartialPage { .. } | FetchError::PageLimit { .. } => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Http(e)
+    }
+}
+
+// Errors returned to API clients. Each kind has its own status code and is
+// named in the body so clients can tell them apart.
+#[derive(Debug, Clone)]
+enum AppError {
+    // Upstream could not be reached or timed out; clients should retry later
+    UpstreamUnavailable { message: String, retry_after: Duration },
+    // Upstream answered with an error status
+    UpstreamBadResponse(String),
+    // Upstream answered, but the body did not match the schema we read
+    Deserialize(String),
+    // Nothing cached and the upstream fetch outlasted REQUEST_DEADLINE_MS; it
+    // keeps running in the background
+    DeadlineExceeded(String),
+    NotFound(String),
+}
+
+impl AppError {
+    // Only used when nothing is cached, so the failed fetch is what the client sees
+    fn from_fetch(e: &FetchError, retry_after: Duration) -> Self {
+        let message = format!("Validator data is unavailable: {}", e);
+        let mut source = e;
+        while let FetchError::PartialPage { source: inner, .. } = source {
+            source = inner;
+        }
+        match source {
+            FetchError::Http(http) if http.status().is_some() => AppError::UpstreamBadResponse(message),
+            FetchError::Http(http) if http.is_decode() => AppError::Deserialize(message),
+            FetchError::Decode(_) => AppError::Deserialize(message),
+            FetchError::PageLimit { .. } => AppError::UpstreamBadResponse(message),
+            _ => AppError::UpstreamUnavailable { message, retry_after },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::UpstreamUnavailable { .. } => "upstream_unavailable",
+            AppError::UpstreamBadResponse(_) => "upstream_bad_response",
+            AppError::Deserialize(_) => "deserialize",
+            AppError::DeadlineExceeded(_) => "deadline_exceeded",
+            AppError::NotFound(_) => "not_found",
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::UpstreamUnavailable { message, .. }
+            | AppError::UpstreamBadResponse(message)
+            | AppError::Deserialize(message)
+            | AppError::DeadlineExceeded(message)
+            | AppError::NotFound(message) => f.write_str(message),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        use actix_web::http::StatusCode;
+        match self {
+            AppError::UpstreamUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::UpstreamBadResponse(_) | AppError::Deserialize(_) => StatusCode::BAD_GATEWAY,
+            AppError::DeadlineExceeded(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::UpstreamUnavailable { retry_after, .. } = self {
+            // The temporary failure clears up by the next refresh at the latest
+            response.insert_header((actix_web::http::header::RETRY_AFTER, retry_after.as_secs().max(1).to_string()));
+        }
+        response.json(serde_json::json!({ "error": self.to_string(), "kind": self.kind() }))
+    }
+}
+
+fn env_opt<T: std::str::FromStr>(key: &str) -> Option<T> {
+    let value = std::env::var(key).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            log::warn!("Ignoring invalid {}={:?}, using default", key, value);
+            None
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env_opt(key).unwrap_or(default)
+}
+
+// ALLOWED_ORIGINS is a comma-separated origin list, or "*" for any origin.
+// Empty (the default) leaves CORS off so only same-origin callers work.
+fn parse_allowed_origins(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(|o| o.trim().trim_end_matches('/').to_string())
+        .filter(|o| !o.is_empty())
+        .collect()
+}
+
+fn build_cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST"])
+        .allowed_headers(vec![actix_web::http::header::CONTENT_TYPE, actix_web::http::header::AUTHORIZATION])
+        .expose_headers(vec!["X-Cache-Status", "X-Request-Id"])
+        .max_age(3600);
+
+    if origins.iter().any(|o| o == "*") {
+        return cors.allow_any_origin();
+    }
+    origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+    body_timeout: Duration,
+) -> Result<T, FetchError> {
+    tokio::time::timeout(body_timeout, response.json())
+        .await
+        .map_err(|_| {
+            let err = FetchError::BodyTimeout(body_timeout);
+            log::warn!("{}", err);
+            err
+        })?
+        .map_err(FetchError::from)
+}
+
+// Entity fields our Flare* structs read, as dotted paths, under mainnet names
+const EXPECTED_ENTITY_FIELDS: &[&str] = &[
+    "id",
+    "display_name",
+    "denormalizedentity",
+    "denormalizedentity.id",
+    "denormalizedentity.node_ids",
+    "denormalizedentity.public_key",
+    "denormalizedentity.submit_signatures_address",
+    "denormalizedentity.submit_address",
+    "denormalizedentity.signing_policy_address",
+    "denormalizedentity.delegation_address",
+    "denormalizedentity.rewards_signed",
+    "denormalizedentity.uptime_signed",
+    "entityminimalconditions",
+    "entityminimalconditions.ftso_scaling",
+    "entityminimalconditions.ftso_fast_updates",
+    "entityminimalconditions.fdc",
+    "entityminimalconditions.staking",
+    "entityminimalconditions.passes_held",
+    "entityminimalconditions.eligible_for_reward",
+    "entityminimalconditions.reward_epoch",
+    "rewards",
+    "rewards.reward_rate_wnat",
+    "rewards.reward_rate_mirror",
+    "rewards.reward_rate_pure",
+    "rewards.reward_epoch",
+    "providersuccessrate",
+    "providersuccessrate.primary",
+    "providersuccessrate.secondary",
+    "providersuccessrate.availability",
+    "providersuccessrate.active",
+    "denormalizedsigningpolicy",
+    "denormalizedsigningpolicy.delegation_address",
+];
+
+// EXPECTED_ENTITY_FIELDS under the names this network's explorer uses
+fn expected_entity_fields(network: Network) -> HashSet<String> {
+    EXPECTED_ENTITY_FIELDS
+        .iter()
+        .map(|field| {
+            let upstream = field.strip_prefix("entityminimalconditions.").and_then(|name| {
+                network.condition_aliases().iter().find(|(_, ours)| *ours == name).map(|(upstream, _)| upstream)
+            });
+            match upstream {
+                Some(upstream) => format!("entityminimalconditions.{}", upstream),
+                None => field.to_string(),
+            }
+        })
+        .collect()
+}
+
+// Union of entity keys (and keys of nested objects) across all results
+fn observed_entity_fields(raw: &serde_json::Value) -> HashSet<String> {
+    let mut fields = HashSet::new();
+    let entities = raw.get("results").and_then(|r| r.as_array()).into_iter().flatten();
+    for entity in entities.filter_map(|e| e.as_object()) {
+        for (key, value) in entity {
+            fields.insert(key.clone());
+            if let Some(nested) = value.as_object() {
+                fields.extend(nested.keys().map(|sub| format!("{}.{}", key, sub)));
+            }
+        }
+    }
+    fields
+}
+
+fn sorted(fields: impl Iterator<Item = String>) -> Vec<String> {
+    let mut fields: Vec<String> = fields.collect();
+    fields.sort();
+    fields
+}
+
+fn record_schema_drift(state: &AppState, observed: HashSet<String>) {
+    let expected = expected_entity_fields(state.network);
+
+    let mut report_slot = state.schema_report.write();
+    let (appeared, vanished) = match report_slot.as_ref() {
+        Some(previous) => {
+            let previous: HashSet<String> = previous.observed_fields.iter().cloned().collect();
+            (
+                sorted(observed.difference(&previous).cloned()),
+                sorted(previous.difference(&observed).cloned()),
+            )
+        },
+        None => (Vec::new(), Vec::new()),
+    };
+
+    let report = SchemaReport {
+        checked_at: chrono::Utc::now().to_rfc3339(),
+        missing_expected: sorted(expected.difference(&observed).cloned()),
+        unknown_fields: sorted(observed.difference(&expected).cloned()),
+        appeared,
+        vanished,
+        observed_fields: sorted(observed.into_iter()),
+    };
+
+    if !report.missing_expected.is_empty() {
+        log::warn!("Upstream schema drift: expected fields missing: {:?}", report.missing_expected);
+    }
+    if !report.appeared.is_empty() {
+        log::warn!("Upstream schema drift: new fields appeared: {:?}", report.appeared);
+    }
+    if !report.vanished.is_empty() {
+        log::warn!("Upstream schema drift: fields vanished: {:?}", report.vanished);
+    }
+
+    *report_slot = Some(report);
+}
+
+fn field_coverage(entities: &[FlareEntity]) -> FieldCoverage {
+    let missing = |absent: fn(&FlareEntity) -> bool| entities.iter().filter(|e| absent(e)).count();
+    FieldCoverage {
+        entities: entities.len(),
+        missing_conditions: missing(|e| e.entityminimalconditions.is_none()),
+        missing_rewards: missing(|e| e.rewards.is_none()),
+        missing_provider_stats: missing(|e| e.providersuccessrate.is_none()),
+    }
+}
+
+// Unlike the opt-in schema diagnostics this runs on every fetch: a missing
+// object deserializes to None without error, so mass absence is the only sign
+fn record_field_coverage(state: &AppState, entities: &[FlareEntity]) {
+    let coverage = field_coverage(entities);
+    let mostly_missing: Vec<&str> = [
+        ("entityminimalconditions", coverage.missing_conditions),
+        ("rewards", coverage.missing_rewards),
+        ("providersuccessrate", coverage.missing_provider_stats),
+    ]
+    .into_iter()
+    .filter(|&(_, missing)| missing as f64 > coverage.entities as f64 * MISSING_FIELD_WARN_SHARE)
+    .map(|(field, _)| field)
+    .collect();
+
+    if !mostly_missing.is_empty() {
+        log::warn!(
+            "Upstream schema change? Most of {} entities lack {}: {:?}",
+            coverage.entities, mostly_missing.join(", "), coverage
+        );
+    }
+    *state.field_coverage.write() = Some(coverage);
+}
+
+// What the startup check asks of the first fetch beyond it succeeding: some
+// entities, and at least one carrying the conditions or rewards that
+// eligibility is decided on. Every Flare* field but the id is optional, so an
+// incompatible schema still deserializes, just into empty validators.
+fn check_startup_fetch(coverage: &FieldCoverage) -> Result<(), String> {
+    if coverage.entities == 0 {
+        return Err("upstream returned no entities".to_string());
+    }
+    if coverage.missing_conditions == coverage.entities && coverage.missing_rewards == coverage.entities {
+        return Err(format!(
+            "none of the {} entities has entityminimalconditions or rewards, the upstream schema looks incompatible",
+            coverage.entities
+        ));
+    }
+    Ok(())
+}
+
+async fn fetch_entity_page(
+    state: &AppState,
+    offset: usize,
+    observed_fields: &mut HashSet<String>,
+) -> Result<FlareEntityList, FetchError> {
+    let url = format!("{}/entity?limit={}&offset={}", state.flare_api, state.upstream_page_size, offset);
+    let response = state.http_client.get(&url).send().await?.error_for_status()?;
+
+    // Drift is checked against the names upstream sent, before any renaming
+    let page: FlareEntityList = if state.schema_diagnostics || !state.network.condition_aliases().is_empty() {
+        let mut raw: serde_json::Value = read_json(response, state.body_timeout).await?;
+        if state.schema_diagnostics {
+            observed_fields.extend(observed_entity_fields(&raw));
+        }
+        if let Some(entities) = raw.get_mut("results").and_then(|r| r.as_array_mut()) {
+            entities.iter_mut().for_each(|entity| apply_condition_aliases(entity, state.network));
+        }
+        serde_json::from_value(raw).map_err(FetchError::Decode)?
+    } else {
+        read_json(response, state.body_timeout).await?
+    };
+
+    Ok(page)
+}
+
+fn clamp_page_size(requested: usize) -> usize {
+    requested.clamp(1, MAX_UPSTREAM_PAGE_SIZE)
+}
+
+// Exponential backoff with up to 50% random jitter, capped at RETRY_MAX_DELAY_MS
+fn retry_delay(attempt: u32) -> Duration {
+    let base = RETRY_BASE_DELAY_MS.saturating_mul(1 << (attempt - 1).min(16)).min(RETRY_MAX_DELAY_MS);
+    let jitter = rand::rng().random_range(0..=base / 2);
+    Duration::from_millis(base + jitter)
+}
+
+async fn fetch_entity_page_with_retry(
+    state: &AppState,
+    offset: usize,
+    observed_fields: &mut HashSet<String>,
+) -> Result<FlareEntityList, FetchError> {
+    let mut attempt = 0;
+    loop {
+        match fetch_entity_page(state, offset, observed_fields).await {
+            Err(e) if e.is_retryable() && attempt < state.max_retries => {
+                attempt += 1;
+                let delay = retry_delay(attempt);
+                log::warn!(
+                    "Upstream page at offset {} failed (attempt {}/{}), retrying in {}ms: {}",
+                    offset, attempt, state.max_retries + 1, delay.as_millis(), e
+                );
+                tokio::time::sleep(delay).await;
+            },
+            result => return result,
+        }
+    }
+}
+
+// Walks /entity until a short page comes back. The first page is fetched on
+// its own; the rest go out in batches of up to UPSTREAM_CONCURRENCY concurrent
+// requests, sized by the total the first page reports so a small network is
+// not sent speculative pages. Without a total the batches are full. A failure
+// on any page drops the rest of its batch and fails the whole fetch so a
+// truncated list is never cached, and so does running into MAX_UPSTREAM_PAGES
+// without reaching the end. With UPSTREAM_MAX_ENTITIES set only the pages that
+// can hold that many are requested, and the result is cut to it.
+async fn fetch_all_entities(state: &AppState) -> Result<Vec<FlareEntity>, FetchError> {
+    let mut observed_fields = HashSet::new();
+    let page_size = state.upstream_page_size;
+    let max_pages = state.upstream_max_entities
+        .map_or(MAX_UPSTREAM_PAGES, |max| max.div_ceil(page_size).min(MAX_UPSTREAM_PAGES));
+
+    let first = fetch_entity_page_with_retry(state, 0, &mut observed_fields).await?;
+    let total = first.count;
+    // A page is the last one when it is short or brings the list up to the total
+    let is_end = |page_len: usize, fetched: usize| page_len < page_size || total.is_some_and(|n| fetched >= n);
+    let mut reached_end = is_end(first.results.len(), first.results.len());
+    let mut entities = first.results;
+    // Past the expected pages (a total that grew meanwhile) probe one at a time
+    let expected_pages = total.map_or(max_pages, |n| n.div_ceil(page_size));
+    let mut next_page = 1;
+
+    while !reached_end && next_page < max_pages {
+        let batch_len = expected_pages.saturating_sub(next_page).clamp(1, UPSTREAM_CONCURRENCY);
+        let batch_end = (next_page + batch_len).min(max_pages);
+        let requests = (next_page..batch_end).map(|page| async move {
+            let offset = page * page_size;
+            let mut fields = HashSet::new();
+            fetch_entity_page_with_retry(state, offset, &mut fields).await
+                .map(|page| (page.results, fields))
+                .map_err(|e| (offset, e))
+        });
+
+        // try_join_all keeps input order, so pages merge deterministically
+        let pages = futures::future::try_join_all(requests).await.map_err(|(offset, e)| {
+            let err = FetchError::PartialPage {
+                offset,
+                fetched: entities.len(),
+                source: Box::new(e),
+            };
+            log::warn!("{}", err);
+            err
+        })?;
+
+        for (results, fields) in pages {
+            observed_fields.extend(fields);
+            // Anything after the last page is past the end, normally empty
+            if reached_end {
+                continue;
+            }
+            reached_end = is_end(results.len(), entities.len() + results.len());
+            entities.extend(results);
+        }
+        next_page = batch_end;
+    }
+
+    match state.upstream_max_entities {
+        Some(max) if entities.len() > max || (!reached_end && entities.len() == max) => {
+            log::warn!("Keeping the first {} entities (UPSTREAM_MAX_ENTITIES), upstream may have more", max);
+            entities.truncate(max);
+        },
+        _ if !reached_end => {
+            let err = FetchError::PageLimit { pages: max_pages, fetched: entities.len() };
+            log::warn!("{}", err);
+            return Err(err);
+        },
+        _ => {},
+    }
+
+    if state.schema_diagnostics {
+        record_schema_drift(state, observed_fields);
+    }
+
+    Ok(entities)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CacheStatus {
+    Hit,
+    // Expired data served while a refresh runs in the background
+    Stale,
+    // Expired data served because the last refresh attempt failed
+    StaleError,
+    // Nothing was cached, the caller waited for upstream
+    Miss,
+}
+
+impl CacheStatus {
+    fn header(self) -> (&'static str, &'static str) {
+        let value = match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Stale => "stale",
+            CacheStatus::StaleError => "stale-error",
+            CacheStatus::Miss => "miss",
+        };
+        ("X-Cache-Status", value)
+    }
+}
+
+// Stale-while-revalidate: fresh data is served as is, expired data is served
+// immediately while a single background refresh is started, and only an empty
+// cache makes the caller wait on upstream. Errors only surface when nothing at
+// all is cached.
+async fn fetch_validator_data(state: &Arc<AppState>) -> Result<(Arc<ValidatorResponse>, CacheStatus), AppError> {
+    // First check cache
+    let expired = {
+        let cache_read = state.cache.read();
+        match &*cache_read {
+            Some(cached) => {
+                let elapsed = SystemTime::now().duration_since(cached.fetched_at).unwrap_or(state.cache_ttl + Duration::from_secs(1));
+                if elapsed < state.cache_ttl {
+                    tracing::debug!(cache = "hit", "Serving cached validator data");
+                    state.metrics.record_cache(CacheStatus::Hit);
+                    return Ok((Arc::clone(&cached.data), CacheStatus::Hit));
+                }
+                Some(Arc::clone(&cached.data))
+            },
+            None => None,
+        }
+    };
+
+    match expired {
+        Some(stale) => {
+            if !state.refresh_in_flight.load(Ordering::Acquire) {
+                let state = Arc::clone(state);
+                actix_web::rt::spawn(async move {
+                    let Some(_guard) = try_begin_refresh(&state) else { return };
+                    if let Err(e) = refresh_validator_data(&state).await {
+                        log::warn!("Revalidation failed, keeping stale data: {}", e);
+                    }
+                });
+            }
+            // Stale data is still better than an error while upstream is down
+            if state.last_refresh_failed.load(Ordering::Acquire) {
+                log::warn!("Serving stale validator data from {} after a failed refresh", stale.timestamp);
+                state.metrics.record_cache(CacheStatus::StaleError);
+                return Ok((stale, CacheStatus::StaleError));
+            }
+            tracing::debug!(cache = "stale", "Serving stale validator data while revalidating");
+            state.metrics.record_cache(CacheStatus::Stale);
+            Ok((stale, CacheStatus::Stale))
+        },
+        None => {
+            state.metrics.record_cache(CacheStatus::Miss);
+            tracing::info!(cache = "miss", "Waiting for validator data from upstream");
+            let started = Instant::now();
+            let finished = match state.request_deadline {
+                Some(deadline) => tokio::time::timeout(deadline, wait_for_refresh(state)).await.is_ok(),
+                None => {
+                    wait_for_refresh(state).await;
+                    true
+                },
+            };
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            if let Some(cached) = state.cache.read().as_ref() {
+                tracing::info!(cache = "miss", elapsed_ms, "Upstream fetch finished");
+                return Ok((Arc::clone(&cached.data), CacheStatus::Miss));
+            }
+            if let (false, Some(deadline)) = (finished, state.request_deadline) {
+                tracing::warn!(cache = "miss", deadline_ms = deadline.as_millis() as u64, "Upstream fetch outlasted the request deadline");
+                return Err(AppError::DeadlineExceeded(format!(
+                    "Validator data is unavailable: upstream did not answer within {}ms, still fetching",
+                    deadline.as_millis()
+                )));
+            }
+            let e = state.last_refresh_error.lock().clone().unwrap_or_else(|| AppError::UpstreamUnavailable {
+                message: "Validator data is unavailable: the upstream fetch did not complete".to_string(),
+                retry_after: state.cache_ttl,
+            });
+            tracing::warn!(cache = "miss", elapsed_ms, error = %e, "Upstream fetch failed");
+            Err(e)
+        },
+    }
+}
+
+// Waits for the refresh that fills an empty cache, starting one unless one is
+// already running, so however many requests miss at once upstream only sees a
+// single crawl. It runs in its own task: a request giving up at its deadline
+// doesn't cancel it, and it still fills the cache for the next one.
+async fn wait_for_refresh(state: &Arc<AppState>) {
+    let finished = state.refresh_finished.notified();
+    tokio::pin!(finished);
+    // Registered before looking, so a refresh ending in between still wakes us
+    finished.as_mut().enable();
+    if state.cache.read().is_some() {
+        return;
+    }
+
+    if !state.refresh_in_flight.load(Ordering::Acquire) {
+        let state = Arc::clone(state);
+        actix_web::rt::spawn(async move {
+            let Some(_guard) = try_begin_refresh(&state) else { return };
+            if let Err(e) = refresh_validator_data(&state).await {
+                log::warn!("Fetch for an empty cache failed: {}", e);
+            }
+        });
+    }
+    finished.await;
+}
+
+// Clears the in-flight flag and wakes waiting requests when the refresh
+// finishes, even on error or panic
+struct RefreshGuard<'a>(&'a AppState);
+
+impl Drop for RefreshGuard<'_> {
+    fn drop(&mut self) {
+        self.0.refresh_in_flight.store(false, Ordering::Release);
+        self.0.refresh_finished.notify_waiters();
+    }
+}
+
+// None when another refresh is already running
+fn try_begin_refresh(state: &AppState) -> Option<RefreshGuard<'_>> {
+    state.refresh_in_flight
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .ok()
+        .map(|_| RefreshGuard(state))
+}
+
+// Fetches from upstream unconditionally and replaces the cache
+async fn refresh_validator_data(state: &AppState) -> Result<Arc<ValidatorResponse>, FetchError> {
+    let started = Instant::now();
+    let result = fetch_all_entities(state).await;
+    state.metrics.record_fetch(started.elapsed(), result.is_ok());
+    state.last_refresh_failed.store(result.is_err(), Ordering::Release);
+    *state.last_refresh_error.lock() = result.as_ref().err().map(|e| AppError::from_fetch(e, state.cache_ttl));
+    let entities = result?;
+    record_field_coverage(state, &entities);
+
+    let response = build_validator_response(
+        &entities,
+        &state.criteria,
+        state.epochs_per_year,
+        state.availability_scale,
+        &state.history.read(),
+        state.reward_avg_window,
+        state.dedup_entities,
+    );
+
+    let fetched_at = SystemTime::now();
+    let cached = CachedData::new(response, fetched_at, state.warm_derived_views);
+    let response = Arc::clone(&cached.data);
+    *state.cache.write() = Some(cached);
+    // The full list is now the authority on every id
+    state.entity_cache.lock().clear();
+    state.ready.store(true, Ordering::Release);
+
+    if let Some(path) = &state.cache_file {
+        save_cache(path, &response, fetched_at).await;
+    }
+    record_snapshot(state, &response);
+    state.metrics.eligible_validators.store(response.eligible_count as u64, Ordering::Relaxed);
+    state.metrics.ineligible_validators.store(response.ineligible_count as u64, Ordering::Relaxed);
+    // Only fails when nobody is subscribed
+    let _ = state.refresh_events.send(RefreshEvent::from(&*response));
+
+    Ok(response)
+}
+
+// Writes to a temporary file then renames it so a crash mid-write never leaves
+// a truncated cache behind. Failures are logged; the in-memory cache is what
+// matters while running. `fetched_at` is kept so a restart judges the data by
+// its real age, not by when it was written.
+async fn save_cache(path: &std::path::Path, data: &ValidatorResponse, fetched_at: SystemTime) {
+    let persisted = PersistedCache { saved_at: fetched_at, data: data.clone() };
+    let bytes = match serde_json::to_vec(&persisted) {
+        Ok(bytes) => bytes,
+        Err(e) => return log::warn!("Failed to serialize cache: {}", e),
+    };
+
+    let tmp = path.with_extension("tmp");
+    let result = match tokio::fs::write(&tmp, bytes).await {
+        Ok(()) => tokio::fs::rename(&tmp, path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log::warn!("Failed to write cache file {}: {}", path.display(), e);
+    }
+}
+
+// Returns the persisted cache when it is readable and younger than the TTL
+fn load_cache(path: &std::path::Path, ttl: Duration) -> Option<(ValidatorResponse, SystemTime)> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            log::warn!("Ignoring unreadable cache file {}: {}", path.display(), e);
+            return None;
+        },
+    };
+    let persisted: PersistedCache = match serde_json::from_slice(&bytes) {
+        Ok(persisted) => persisted,
+        Err(e) => {
+            log::warn!("Ignoring corrupt cache file {}: {}", path.display(), e);
+            return None;
+        },
+    };
+
+    let age = SystemTime::now().duration_since(persisted.saved_at).unwrap_or(Duration::MAX);
+    if age >= ttl {
+        log::info!("Ignoring cache file {}, it is {}s old", path.display(), age.as_secs());
+        return None;
+    }
+    Some((persisted.data, persisted.saved_at))
+}
+
+// Everything between the upstream fetch and the cache: maps entities to
+// validators, fills in trailing averages from history and splits by eligibility
+fn build_validator_response(
+    entities: &[FlareEntity],
+    criteria: &EligibilityCriteria,
+    epochs_per_year: f64,
+    availability_scale: f64,
+    history: &VecDeque<Snapshot>,
+    reward_avg_window: usize,
+    dedup: bool,
+) -> ValidatorResponse {
+    let mut validators: Vec<Validator> = entities.iter()
+        .map(|entity| process_entity(entity, epochs_per_year, availability_scale))
+        .collect();
+    if dedup {
+        validators = dedup_validators(validators, epochs_per_year);
+    }
+    apply_trailing_averages(&mut validators, history, reward_avg_window);
+    let total_validators = validators.len();
+    let (eligible_nodes, ineligible_nodes) = split_validators(validators, &HashSet::new(), criteria);
+
+    ValidatorResponse {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        total_validators,
+        eligible_count: eligible_nodes.len(),
+        ineligible_count: ineligible_nodes.len(),
+        eligible_nodes,
+        ineligible_nodes,
+        reward_epoch: current_reward_epoch(entities),
+    }
+}
+
+// Entities are reported per epoch, so the newest one any entity names is the
+// current one; a straggler still on the previous epoch does not pull it back
+fn current_reward_epoch(entities: &[FlareEntity]) -> Option<u64> {
+    entities.iter()
+        .filter_map(|entity| {
+            entity.rewards.as_ref().and_then(|r| r.reward_epoch)
+                .or_else(|| entity.entityminimalconditions.as_ref().and_then(|c| c.reward_epoch))
+        })
+        .max()
+}
+
+// Merges entities that share a delegation address or any node id, directly or
+// through a chain of other entities, into one validator. Groups keep the
+// position of their first member.
+fn dedup_validators(validators: Vec<Validator>, epochs_per_year: f64) -> Vec<Validator> {
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    let mut parent: Vec<usize> = (0..validators.len()).collect();
+    let mut first_with_key: HashMap<String, usize> = HashMap::new();
+    for (i, v) in validators.iter().enumerate() {
+        let keys = v.delegation_address.iter()
+            .map(|address| format!("address:{}", address.to_lowercase()))
+            .chain(v.node_ids.iter().map(|node_id| format!("node:{}", node_id)));
+        for key in keys {
+            match first_with_key.get(&key) {
+                Some(&j) => {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[a.max(b)] = a.min(b);
+                },
+                None => {
+                    first_with_key.insert(key, i);
+                },
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<Validator>> = Vec::new();
+    let mut group_of_root: HashMap<usize, usize> = HashMap::new();
+    for (i, v) in validators.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        let group = *group_of_root.entry(r).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(v);
+    }
+
+    groups.into_iter().map(|group| merge_validators(group, epochs_per_year)).collect()
+}
+
+// Conflicts resolve conservatively so a merge never makes a provider look
+// better than its weakest record: a condition holds only if every record with
+// conditions data meets it, passes and availability take the minimum, and
+// `active` needs every reporting record active. Reward rates are per unit of
+// stake, so they are averaged rather than summed. The lowest id and its name
+// represent the group; node_ids are unioned.
+fn merge_validators(mut group: Vec<Validator>, epochs_per_year: f64) -> Validator {
+    if group.len() == 1 {
+        return group.remove(0);
+    }
+    group.sort_by_key(|v| v.id);
+
+    let mut node_ids: Vec<String> = Vec::new();
+    for node_id in group.iter().flat_map(|v| &v.node_ids) {
+        if !node_ids.contains(node_id) {
+            node_ids.push(node_id.clone());
+        }
+    }
+
+    let conditions = group.iter()
+        .filter_map(|v| v.conditions.clone())
+        .reduce(|a, b| Conditions {
+            ftso_anchor_feeds: a.ftso_anchor_feeds && b.ftso_anchor_feeds,
+            ftso_block_latency_feeds: a.ftso_block_latency_feeds && b.ftso_block_latency_feeds,
+            fdc: a.fdc && b.fdc,
+            staking: a.staking && b.staking,
+            passes: a.passes.min(b.passes),
+            passes_held: match (a.passes_held, b.passes_held) {
+                (Some(x), Some(y)) => Some(x.min(y)),
+                (x, y) => x.or(y),
+            },
+            eligible_for_reward: a.eligible_for_reward && b.eligible_for_reward,
+        });
+
+    let rates: Vec<&RewardRates> = group.iter().filter_map(|v| v.reward_rates.as_ref()).collect();
+    let reward_rates = (!rates.is_empty()).then(|| {
+        let mean = |field: fn(&RewardRates) -> f64| rates.iter().map(|r| field(r)).sum::<f64>() / rates.len() as f64;
+        let (wnat, mirror, pure) = (mean(|r| r.wnat), mean(|r| r.mirror), mean(|r| r.pure));
+        let combined = wnat + mirror + pure;
+        RewardRates {
+            wnat,
+            mirror,
+            pure,
+            combined,
+            apy: annualize(combined, epochs_per_year),
+            combined_avg: combined,
+            combined_avg_fallback: true,
+            normalized_combined: None,
+            units: RateUnits::new(epochs_per_year),
+        }
+    });
+
+    let stats: Vec<&ProviderStats> = group.iter().filter_map(|v| v.provider_stats.as_ref()).collect();
+    let provider_stats = (!stats.is_empty()).then(|| {
+        let sum = |field: fn(&ProviderStats) -> Option<u32>| {
+            stats.iter().filter_map(|s| field(s)).reduce(|a, b| a.saturating_add(b))
+        };
+        ProviderStats {
+            primary: sum(|s| s.primary),
+            secondary: sum(|s| s.secondary),
+            availability: stats.iter().filter_map(|s| s.availability).reduce(f64::min),
+            active: stats.iter().filter_map(|s| s.active).reduce(|a, b| a && b),
+        }
+    });
+    let rewards_signed = group.iter().filter_map(|v| v.rewards_signed).min();
+    let uptime_signed = group.iter().filter_map(|v| v.uptime_signed).min();
+
+    let mut rest = group.split_off(1);
+    let primary = group.remove(0);
+    let delegation_address = primary.delegation_address.clone()
+        .or_else(|| rest.iter_mut().find_map(|v| v.delegation_address.take()));
+
+    Validator {
+        id: primary.id,
+        name: primary.name,
+        node_id: node_ids.first().cloned(),
+        node_ids,
+        delegation_address,
+        conditions,
+        provider_stats,
+        estimated_apy: reward_rates.as_ref().map(|r| annualize(r.combined, epochs_per_year)),
+        reward_rates,
+        rewards_signed,
+        uptime_signed,
+        rate_vs_median: None,
+        unmet_criteria: None,
+        ineligibility_reasons: None,
+        merged_ids: rest.iter().map(|v| v.id).collect(),
+    }
+}
+
+// Sets normalized_combined from bounds over `validators`. A component that is
+// the same for everyone (or not a finite number) adds 0.
+fn normalize_reward_rates(validators: &mut [Validator]) {
+    let components: [fn(&RewardRates) -> f64; 3] = [|r| r.wnat, |r| r.mirror, |r| r.pure];
+    let bounds: Vec<Option<(f64, f64)>> = components.iter()
+        .map(|component| {
+            validators.iter()
+                .filter_map(|v| v.reward_rates.as_ref())
+                .map(component)
+                .filter(|x| x.is_finite())
+                .fold(None, |bounds, x| match bounds {
+                    None => Some((x, x)),
+                    Some((lo, hi)) => Some((x.min(lo), x.max(hi))),
+                })
+        })
+        .collect();
+
+    for rates in validators.iter_mut().filter_map(|v| v.reward_rates.as_mut()) {
+        let normalized = components.iter().zip(&bounds)
+            .map(|(component, bounds)| match *bounds {
+                Some((lo, hi)) if hi > lo && component(rates).is_finite() => (component(rates) - lo) / (hi - lo),
+                _ => 0.0,
+            })
+            .sum();
+        rates.normalized_combined = Some(normalized);
+    }
+}
+
+// Splits validators into (eligible, ineligible), ranking the eligible ones by
+// combined reward rate and annotating them with rate_vs_median and
+// normalized_combined
+fn split_validators(
+    validators: Vec<Validator>,
+    ignore: &HashSet<ConditionFlag>,
+    criteria: &EligibilityCriteria,
+) -> (Vec<Validator>, Vec<Validator>) {
+    let (mut eligible_nodes, mut ineligible_nodes): (Vec<_>, Vec<_>) = validators.into_iter()
+        .partition(|v| is_eligible_ignoring(v, ignore, criteria));
+
+    eligible_nodes.sort_by(rank_order);
+
+    let rates: Vec<f64> = eligible_nodes.iter().map(combined_rate).collect();
+    let median = median(&rates).filter(|m| *m > 0.0);
+    for validator in &mut eligible_nodes {
+        validator.rate_vs_median = median.map(|m| combined_rate(validator) / m);
+    }
+    for validator in &mut eligible_nodes {
+        validator.unmet_criteria = None;
+    }
+    normalize_reward_rates(&mut eligible_nodes);
+
+    // Near-misses first, then by id, so paging through the list is stable
+    for validator in &mut ineligible_nodes {
+        validator.rate_vs_median = None;
+        if let Some(rates) = validator.reward_rates.as_mut() {
+            rates.normalized_combined = None;
+        }
+        validator.unmet_criteria = Some(unmet_criteria(validator, ignore, criteria));
+    }
+    ineligible_nodes.sort_by_key(|v| (v.unmet_criteria, v.id));
+
+    (eligible_nodes, ineligible_nodes)
+}
+
+// Requirements a validator must meet to be eligible, loaded from env so they
+// can follow Flare's rules without a rebuild. Defaults match the original
+// strict check: every flag required and at least 3 passes.
+#[derive(Debug, Clone)]
+struct EligibilityCriteria {
+    min_passes: u8,
+    require_ftso_anchor_feeds: bool,
+    require_ftso_block_latency_feeds: bool,
+    require_fdc: bool,
+    require_staking: bool,
+    require_eligible_for_reward: bool,
+    min_combined_rate: Option<f64>,
+}
+
+impl Default for EligibilityCriteria {
+    fn default() -> Self {
+        EligibilityCriteria {
+            min_passes: 3,
+            require_ftso_anchor_feeds: true,
+            require_ftso_block_latency_feeds: true,
+            require_fdc: true,
+            require_staking: true,
+            require_eligible_for_reward: true,
+            min_combined_rate: None,
+        }
+    }
+}
+
+impl EligibilityCriteria {
+    fn from_env() -> Self {
+        let defaults = EligibilityCriteria::default();
+        EligibilityCriteria {
+            min_passes: env_or("MIN_PASSES", defaults.min_passes),
+            require_ftso_anchor_feeds: env_or("REQUIRE_FTSO_ANCHOR_FEEDS", defaults.require_ftso_anchor_feeds),
+            require_ftso_block_latency_feeds: env_or("REQUIRE_FTSO_BLOCK_LATENCY_FEEDS", defaults.require_ftso_block_latency_feeds),
+            require_fdc: env_or("REQUIRE_FDC", defaults.require_fdc),
+            require_staking: env_or("REQUIRE_STAKING", defaults.require_staking),
+            require_eligible_for_reward: env_or("REQUIRE_ELIGIBLE_FOR_REWARD", defaults.require_eligible_for_reward),
+            min_combined_rate: env_opt("MIN_ELIGIBLE_COMBINED_RATE"),
+        }
+    }
+
+    fn requires(&self, flag: ConditionFlag) -> bool {
+        match flag {
+            ConditionFlag::FtsoAnchorFeeds => self.require_ftso_anchor_feeds,
+            ConditionFlag::FtsoBlockLatencyFeeds => self.require_ftso_block_latency_feeds,
+            ConditionFlag::Fdc => self.require_fdc,
+            ConditionFlag::Staking => self.require_staking,
+            ConditionFlag::Passes => self.min_passes > 0,
+            ConditionFlag::EligibleForReward => self.require_eligible_for_reward,
+        }
+    }
+}
+
+// Condition flags that can be excluded from the eligibility check with ?ignore=
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ConditionFlag {
+    FtsoAnchorFeeds,
+    FtsoBlockLatencyFeeds,
+    Fdc,
+    Staking,
+    Passes,
+    EligibleForReward,
+}
+
+impl ConditionFlag {
+    const ALL: [ConditionFlag; 6] = [
+        ConditionFlag::FtsoAnchorFeeds,
+        ConditionFlag::FtsoBlockLatencyFeeds,
+        ConditionFlag::Fdc,
+        ConditionFlag::Staking,
+        ConditionFlag::Passes,
+        ConditionFlag::EligibleForReward,
+    ];
+}
+
+impl std::str::FromStr for ConditionFlag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ftso_anchor_feeds" => Ok(ConditionFlag::FtsoAnchorFeeds),
+            "ftso_block_latency_feeds" => Ok(ConditionFlag::FtsoBlockLatencyFeeds),
+            "fdc" => Ok(ConditionFlag::Fdc),
+            "staking" => Ok(ConditionFlag::Staking),
+            "passes" => Ok(ConditionFlag::Passes),
+            "eligible_for_reward" => Ok(ConditionFlag::EligibleForReward),
+            _ => Err(format!(
+                "Unknown condition '{}', expected one of: ftso_anchor_feeds, \
+                 ftso_block_latency_feeds, fdc, staking, passes, eligible_for_reward",
+                s
+            )),
+        }
+    }
+}
+
+// OpenAPI description of the list query, shared by the v1 and v2 routes. The
+// handlers read the raw map through PageRequest, ListSort, ValidatorFilter and
+// FieldMask; list_query_params_are_documented keeps the two in step
+#[derive(utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+#[allow(dead_code)]
+struct ListPageParams {
+    /// combined, wnat, mirror, pure, availability or name
+    sort_by: Option<String>,
+    /// asc or desc
+    order: Option<String>,
+    /// Validators to skip
+    offset: Option<usize>,
+    /// Page size, default 100, at most 500
+    limit: Option<usize>,
+}
+
+// Filters, projection and format, also taken by /top after its own limit and offset
+#[derive(utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+#[allow(dead_code)]
+struct ListFilterParams {
+    /// Comma-separated conditions to disregard: ftso_anchor_feeds, ftso_block_latency_feeds, fdc, staking, passes, eligible_for_reward
+    ignore: Option<String>,
+    /// Drop validators below this availability
+    min_availability: Option<f64>,
+    /// Drop validators below this combined rate
+    min_combined_reward: Option<f64>,
+    /// Keep only validators with this active flag
+    active: Option<bool>,
+    /// Keep only validators with this staking condition
+    staking: Option<bool>,
+    /// Drop validators with a lower or unknown uptime_signed
+    min_uptime_signed: Option<u32>,
+    /// Comma-separated fields to keep in each validator, dotted for nested ones
+    fields: Option<String>,
+    /// Reject unknown names in fields instead of ignoring them
+    strict: Option<bool>,
+    /// json (default), yaml, csv or msgpack; overrides Accept
+    format: Option<String>,
+}
+
+// ?offset= and ?limit= for the list endpoints, limit clamped to MAX_PAGE_LIMIT
+#[derive(Debug, Clone, Copy)]
+struct PageRequest {
+    offset: usize,
+    limit: usize,
+}
+
+impl PageRequest {
+    fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        let offset = match query.get("offset").map(|o| o.parse::<usize>()) {
+            None => 0,
+            Some(Ok(offset)) => offset,
+            Some(Err(_)) => return Err("'offset' must be a non-negative integer".to_string()),
+        };
+        let limit = match query.get("limit").map(|l| l.parse::<usize>()) {
+            None => DEFAULT_PAGE_LIMIT,
+            Some(Ok(limit)) if limit > 0 => limit.min(MAX_PAGE_LIMIT),
+            Some(_) => return Err("'limit' must be a positive integer".to_string()),
+        };
+        Ok(PageRequest { offset, limit })
+    }
+
+    // Errors rather than returning an empty page past the end of the list
+    fn over(&self, total: usize) -> Result<Pagination, String> {
+        if self.offset > total {
+            return Err(format!("'offset' {} is past the end of the list ({} validators)", self.offset, total));
+        }
+        Ok(Pagination {
+            total,
+            offset: self.offset,
+            limit: self.limit,
+            has_more: self.offset + self.limit < total,
+        })
+    }
+
+    fn slice<'a, T>(&self, items: &'a [T]) -> &'a [T] {
+        let start = self.offset.min(items.len());
+        let end = start.saturating_add(self.limit).min(items.len());
+        &items[start..end]
+    }
+}
+
+// ELIGIBLE_LIST_CAP bounds what an unpaged request gets. A request that pages
+// with ?offset=/?limit= walks the full list instead, otherwise the validators
+// past the cap could never be reached. Returns whether the list was cut.
+fn apply_list_cap<T>(validators: &mut Vec<T>, cap: Option<usize>, paged: bool) -> bool {
+    match cap {
+        Some(cap) if !paged && validators.len() > cap => {
+            validators.truncate(cap);
+            true
+        },
+        _ => false,
+    }
+}
+
+fn parse_ignore(query: &HashMap<String, String>) -> Result<HashSet<ConditionFlag>, String> {
+    match query.get("ignore") {
+        Some(flags) => flags.split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(str::parse)
+            .collect(),
+        None => Ok(HashSet::new()),
+    }
+}
+
+// Re-splits cached data with some conditions ignored, keeping the fetch timestamp.
+// Without anything to ignore the cached data is handed back as is.
+fn resplit(
+    data: Arc<ValidatorResponse>,
+    ignore: &HashSet<ConditionFlag>,
+    criteria: &EligibilityCriteria,
+) -> Arc<ValidatorResponse> {
+    if ignore.is_empty() {
+        return data;
+    }
+
+    let validators = data.eligible_nodes.iter().chain(&data.ineligible_nodes).cloned().collect();
+    let (eligible_nodes, ineligible_nodes) = split_validators(validators, ignore, criteria);
+
+    Arc::new(ValidatorResponse {
+        timestamp: data.timestamp.clone(),
+        total_validators: data.total_validators,
+        eligible_count: eligible_nodes.len(),
+        ineligible_count: ineligible_nodes.len(),
+        eligible_nodes,
+        ineligible_nodes,
+        reward_epoch: data.reward_epoch,
+    })
+}
+
+// Handlers reorder and page borrowed lists and only copy what they return
+fn to_owned_page(validators: &[&Validator]) -> Vec<Validator> {
+    validators.iter().map(|&v| v.clone()).collect()
+}
+
+// Server-side filters for the list endpoints; unset fields don't filter, and a
+// validator missing the filtered field never matches
+#[derive(Debug, Default)]
+struct ValidatorFilter {
+    min_availability: Option<f64>,
+    min_combined_reward: Option<f64>,
+    active: Option<bool>,
+    staking: Option<bool>,
+    min_uptime_signed: Option<u32>,
+}
+
+impl ValidatorFilter {
+    fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        fn number(query: &HashMap<String, String>, key: &str) -> Result<Option<f64>, String> {
+            query.get(key)
+                .map(|v| v.parse::<f64>().ok().filter(|n| n.is_finite())
+                    .ok_or_else(|| format!("'{}' must be a number, got '{}'", key, v)))
+                .transpose()
+        }
+        fn flag(query: &HashMap<String, String>, key: &str) -> Result<Option<bool>, String> {
+            query.get(key)
+                .map(|v| v.parse::<bool>()
+                    .map_err(|_| format!("'{}' must be 'true' or 'false', got '{}'", key, v)))
+                .transpose()
+        }
+
+        Ok(ValidatorFilter {
+            min_availability: number(query, "min_availability")?,
+            min_combined_reward: number(query, "min_combined_reward")?,
+            active: flag(query, "active")?,
+            staking: flag(query, "staking")?,
+            min_uptime_signed: query.get("min_uptime_signed")
+                .map(|v| v.parse::<u32>()
+                    .map_err(|_| format!("'min_uptime_signed' must be a non-negative integer, got '{}'", v)))
+                .transpose()?,
+        })
+    }
+
+    fn matches(&self, validator: &Validator) -> bool {
+        let stats = validator.provider_stats.as_ref();
+        let combined = validator.reward_rates.as_ref().map(|r| r.combined);
+        let staking = validator.conditions.as_ref().map(|c| c.staking);
+
+        self.min_availability.is_none_or(|min| stats.and_then(|s| s.availability).is_some_and(|a| a >= min))
+            && self.min_combined_reward.is_none_or(|min| combined.is_some_and(|c| c >= min))
+            && self.active.is_none_or(|want| stats.and_then(|s| s.active) == Some(want))
+            && self.staking.is_none_or(|want| staking == Some(want))
+            && self.min_uptime_signed.is_none_or(|min| validator.uptime_signed.is_some_and(|u| u >= min))
+    }
+
+    fn apply<'a>(&self, validators: &'a [Validator]) -> Vec<&'a Validator> {
+        validators.iter().filter(|v| self.matches(v)).collect()
+    }
+}
+
+// Fields the list endpoints can be ordered by with ?sort_by=
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Combined,
+    Wnat,
+    Mirror,
+    Pure,
+    Availability,
+    Name,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "combined" => Ok(SortKey::Combined),
+            "wnat" => Ok(SortKey::Wnat),
+            "mirror" => Ok(SortKey::Mirror),
+            "pure" => Ok(SortKey::Pure),
+            "availability" => Ok(SortKey::Availability),
+            "name" => Ok(SortKey::Name),
+            _ => Err(format!(
+                "Unknown sort_by '{}', expected one of: combined, wnat, mirror, pure, availability, name",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ListSort {
+    key: SortKey,
+    descending: bool,
+}
+
+impl ListSort {
+    // None when neither param is given, so handlers keep their default ordering
+    fn from_query(query: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        let key = query.get("sort_by").map(|k| k.parse::<SortKey>()).transpose()?;
+        let descending = match query.get("order").map(String::as_str) {
+            None => None,
+            Some("desc") => Some(true),
+            Some("asc") => Some(false),
+            Some(other) => return Err(format!("Unknown order '{}', expected 'asc' or 'desc'", other)),
+        };
+        if key.is_none() && descending.is_none() {
+            return Ok(None);
+        }
+
+        let key = key.unwrap_or(SortKey::Combined);
+        Ok(Some(ListSort {
+            key,
+            // Names read naturally A-Z, rates and availability best-first
+            descending: descending.unwrap_or(key != SortKey::Name),
+        }))
+    }
+
+    fn value(&self, validator: &Validator) -> Option<f64> {
+        let rates = validator.reward_rates.as_ref();
+        match self.key {
+            SortKey::Combined => rates.map(|r| r.combined),
+            SortKey::Wnat => rates.map(|r| r.wnat),
+            SortKey::Mirror => rates.map(|r| r.mirror),
+            SortKey::Pure => rates.map(|r| r.pure),
+            SortKey::Availability => validator.provider_stats.as_ref().and_then(|s| s.availability),
+            SortKey::Name => None,
+        }
+    }
+
+    // Missing values go last in either direction; ties fall back to id
+    fn apply(&self, validators: &mut [&Validator]) {
+        validators.sort_by(|a, b| {
+            let ordering = if self.key == SortKey::Name {
+                let ordering = a.name.to_lowercase().cmp(&b.name.to_lowercase());
+                if self.descending { ordering.reverse() } else { ordering }
+            } else {
+                match (self.value(a), self.value(b)) {
+                    (Some(x), Some(y)) => {
+                        let ordering = x.total_cmp(&y);
+                        if self.descending { ordering.reverse() } else { ordering }
+                    },
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            };
+            ordering.then(a.id.cmp(&b.id))
+        });
+    }
+}
+
+// ?fields=id,name,reward_rates.combined as a tree of the keys to keep in each
+// validator. A node listed itself keeps its whole value, even when some of
+// its children were listed too.
+#[derive(Debug, Default, PartialEq)]
+struct FieldMask {
+    whole: bool,
+    children: HashMap<String, FieldMask>,
+}
+
+impl FieldMask {
+    // Unknown names are dropped, or rejected with ?strict=true
+    fn from_query(query: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        let Some(raw) = query.get("fields") else {
+            return Ok(None);
+        };
+        let strict = match query.get("strict").map(|s| s.parse::<bool>()) {
+            None => false,
+            Some(Ok(strict)) => strict,
+            Some(Err(_)) => return Err("'strict' must be 'true' or 'false'".to_string()),
+        };
+
+        let known = validator_field_paths();
+        let mut mask = FieldMask::default();
+        for path in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if known.contains(path) {
+                mask.insert(path);
+            } else if strict {
+                return Err(format!("Unknown field '{}'", path));
+            }
+        }
+        Ok(Some(mask))
+    }
+
+    fn insert(&mut self, path: &str) {
+        let node = path.split('.').fold(self, |node, key| node.children.entry(key.to_string()).or_default());
+        node.whole = true;
+    }
+
+    fn apply(&self, value: &mut serde_json::Value) {
+        if self.whole {
+            return;
+        }
+        match value {
+            serde_json::Value::Object(map) => {
+                map.retain(|key, _| self.children.contains_key(key));
+                for (key, child) in map.iter_mut() {
+                    self.children[key].apply(child);
+                }
+            },
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            // A null parent or a scalar stays as it is
+            _ => {},
+        }
+    }
+}
+
+// Dotted paths of every Validator field, read from the OpenAPI components so
+// they follow the structs
+fn validator_field_paths() -> &'static HashSet<String> {
+    static PATHS: std::sync::OnceLock<HashSet<String>> = std::sync::OnceLock::new();
+    PATHS.get_or_init(|| {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap_or_default();
+        let schemas = &spec["components"]["schemas"];
+        let mut paths = HashSet::new();
+        collect_field_paths(schemas, &schemas["Validator"], "", &mut paths);
+        paths
+    })
+}
+
+fn collect_field_paths(schemas: &serde_json::Value, schema: &serde_json::Value, prefix: &str, paths: &mut HashSet<String>) {
+    let Some(properties) = schema["properties"].as_object() else {
+        return;
+    };
+    for (name, property) in properties {
+        let path = format!("{}{}", prefix, name);
+        // Nested structs appear as a $ref, wrapped in oneOf when nullable
+        let reference = property["$ref"].as_str()
+            .or_else(|| property["oneOf"].as_array()?.iter().find_map(|s| s["$ref"].as_str()))
+            .or_else(|| property["items"]["$ref"].as_str());
+        if let Some(name) = reference.and_then(|r| r.strip_prefix("#/components/schemas/")) {
+            collect_field_paths(schemas, &schemas[name], &format!("{}.", path), paths);
+        }
+        paths.insert(path);
+    }
+}
+
+// Check eligibility based on our strict criteria
+fn is_eligible(validator: &Validator, criteria: &EligibilityCriteria) -> bool {
+    is_eligible_ignoring(validator, &HashSet::new(), criteria)
+}
+
+fn is_eligible_ignoring(
+    validator: &Validator,
+    ignore: &HashSet<ConditionFlag>,
+    criteria: &EligibilityCriteria,
+) -> bool {
+    unmet_criteria(validator, ignore, criteria) == 0
+}
+
+// Number of criteria the validator fails; without conditions data every
+// required condition flag counts as failed, and missing data is still one
+// failure when all of them are ignored
+fn unmet_criteria(
+    validator: &Validator,
+    ignore: &HashSet<ConditionFlag>,
+    criteria: &EligibilityCriteria,
+) -> usize {
+    let rate_unmet = !criteria.min_combined_rate.is_none_or(|min| combined_rate(validator) >= min);
+
+    let required = ConditionFlag::ALL.iter()
+        .filter(|flag| criteria.requires(**flag) && !ignore.contains(flag));
+    let flags_unmet = match &validator.conditions {
+        Some(cond) => required.filter(|flag| !flag_met(cond, **flag, criteria)).count(),
+        None => required.count().max(1),
+    };
+
+    flags_unmet + usize::from(rate_unmet)
+}
+
+fn flag_met(cond: &Conditions, flag: ConditionFlag, criteria: &EligibilityCriteria) -> bool {
+    match flag {
+        ConditionFlag::FtsoAnchorFeeds => cond.ftso_anchor_feeds,
+        ConditionFlag::FtsoBlockLatencyFeeds => cond.ftso_block_latency_feeds,
+        ConditionFlag::Fdc => cond.fdc,
+        ConditionFlag::Staking => cond.staking,
+        ConditionFlag::Passes => cond.passes >= criteria.min_passes,
+        ConditionFlag::EligibleForReward => cond.eligible_for_reward,
+    }
+}
+
+// Human-readable form of unmet_criteria(); a validator without conditions
+// data gets a single reason instead of one per required flag
+fn ineligibility_reasons(
+    validator: &Validator,
+    ignore: &HashSet<ConditionFlag>,
+    criteria: &EligibilityCriteria,
+) -> Vec<String> {
+    let mut reasons: Vec<String> = match &validator.conditions {
+        None => vec!["no conditions data available".to_string()],
+        Some(cond) => ConditionFlag::ALL.iter()
+            .filter(|flag| criteria.requires(**flag) && !ignore.contains(flag))
+            .filter(|flag| !flag_met(cond, **flag, criteria))
+            .map(|flag| match flag {
+                ConditionFlag::FtsoAnchorFeeds => "missing FTSO anchor feeds".to_string(),
+                ConditionFlag::FtsoBlockLatencyFeeds => "missing FTSO block-latency feeds".to_string(),
+                ConditionFlag::Fdc => "missing FDC".to_string(),
+                ConditionFlag::Staking => "not staking".to_string(),
+                ConditionFlag::Passes => format!("only {} passes held, {} required", cond.passes, criteria.min_passes),
+                ConditionFlag::EligibleForReward => "not eligible_for_reward".to_string(),
+            })
+            .collect(),
+    };
+
+    if let Some(min) = criteria.min_combined_rate.filter(|&min| combined_rate(validator) < min) {
+        reasons.push(format!("combined rate {} below the minimum {}", combined_rate(validator), min));
+    }
+    reasons
+}
+
+fn combined_rate(validator: &Validator) -> f64 {
+    validator.reward_rates.as_ref().map_or(0.0, |r| r.combined)
+}
+
+// Eligible ranking: combined rate descending with NaN lowest, then id, so
+// validators with equal rates keep their places across refreshes
+fn rank_order(a: &Validator, b: &Validator) -> std::cmp::Ordering {
+    let rate = |v: &Validator| Some(combined_rate(v)).filter(|r| !r.is_nan()).unwrap_or(f64::NEG_INFINITY);
+    rate(b).partial_cmp(&rate(a)).unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+// Median of a list sorted in either direction
+fn median(sorted: &[f64]) -> Option<f64> {
+    let len = sorted.len();
+    match len {
+        0 => None,
+        _ if len % 2 == 1 => Some(sorted[len / 2]),
+        _ => Some((sorted[len / 2 - 1] + sorted[len / 2]) / 2.0),
+    }
+}
+
+// Selection weight: combined reward rate scaled by availability, zero when
+// either is unknown
+fn selection_weight(validator: &Validator) -> f64 {
+    let availability = validator.provider_stats.as_ref().and_then(|p| p.availability).unwrap_or(0.0);
+    (combined_rate(validator) * availability).max(0.0)
+}
+
+// Selection weight as a linear combination, from ?w_reward=, ?w_availability=
+// and ?w_uptime= normalized to sum to 1. Each term is the validator's value
+// over the largest one in the pool, so rates around 0.001, availability in
+// percent and uptime counts all contribute on the same 0..1 scale.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+struct ScoreWeights {
+    reward: f64,
+    availability: f64,
+    uptime: f64,
+}
+
+impl ScoreWeights {
+    // None when no weight is given, which keeps selection_weight()
+    fn from_query(query: &HashMap<String, String>) -> Result<Option<Self>, String> {
+        fn weight(query: &HashMap<String, String>, key: &str) -> Result<Option<f64>, String> {
+            query.get(key)
+                .map(|v| v.parse::<f64>().ok().filter(|w| w.is_finite() && *w >= 0.0)
+                    .ok_or_else(|| format!("'{}' must be a non-negative number, got '{}'", key, v)))
+                .transpose()
+        }
+
+        let given = [weight(query, "w_reward")?, weight(query, "w_availability")?, weight(query, "w_uptime")?];
+        if given.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        let [reward, availability, uptime] = given.map(|w| w.unwrap_or(0.0));
+        let sum = reward + availability + uptime;
+        if sum <= 0.0 {
+            return Err("At least one of 'w_reward', 'w_availability' and 'w_uptime' must be positive".to_string());
+        }
+        Ok(Some(ScoreWeights {
+            reward: reward / sum,
+            availability: availability / sum,
+            uptime: uptime / sum,
+        }))
+    }
+
+    // Missing values count as 0, like in selection_weight()
+    fn scores(&self, candidates: &[&Validator]) -> Vec<f64> {
+        let terms: Vec<[f64; 3]> = candidates.iter()
+            .map(|v| [
+                combined_rate(v).max(0.0),
+                v.provider_stats.as_ref().and_then(|p| p.availability).unwrap_or(0.0).max(0.0),
+                v.uptime_signed.unwrap_or(0) as f64,
+            ])
+            .collect();
+        let mut max = [0.0f64; 3];
+        for term in &terms {
+            for (m, x) in max.iter_mut().zip(term) {
+                *m = m.max(*x);
+            }
+        }
+        let weights = [self.reward, self.availability, self.uptime];
+
+        terms.iter()
+            .map(|term| (0..3)
+                .filter(|&k| max[k] > 0.0)
+                .map(|k| weights[k] * term[k] / max[k])
+                .sum())
+            .collect()
+    }
+}
+
+// Weighted sampling without replacement (Efraimidis-Spirakis): each candidate
+// draws key = ln(u) / weight and the largest keys win. Zero-weight candidates
+// are only picked once every positive-weight candidate is taken, in input order.
+fn weighted_sample(weights: &[f64], count: usize, rng: &mut impl Rng) -> Vec<usize> {
+    let mut keyed: Vec<(usize, f64)> = weights.iter()
+        .enumerate()
+        .map(|(i, &w)| {
+            let u: f64 = rng.random_range(f64::MIN_POSITIVE..1.0);
+            let key = if w > 0.0 { u.ln() / w } else { f64::NEG_INFINITY };
+            (i, key)
+        })
+        .collect();
+    keyed.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    keyed.into_iter().take(count).map(|(i, _)| i).collect()
+}
+
+// A single draw for /api/validators/random, returning the index and its chance
+// of being drawn. Weighted draws fall back to uniform when no candidate has a
+// positive weight.
+fn pick_one(weights: &[f64], weighted: bool, rng: &mut impl Rng) -> Option<(usize, f64)> {
+    if weights.is_empty() {
+        return None;
+    }
+    let total: f64 = weights.iter().sum();
+    if weighted && total > 0.0 {
+        let i = weighted_sample(weights, 1, rng)[0];
+        Some((i, weights[i] / total))
+    } else {
+        Some((rng.random_range(0..weights.len()), 1.0 / weights.len() as f64))
+    }
+}
+
+// Water-filling cap: no candidate gets more than max_share of the total, the
+// excess is redistributed over the rest in proportion to their weights.
+// Returns each candidate's share. When there are too few positive-weight
+// candidates for the cap to hold, they all get an equal share.
+fn cap_weight_shares(weights: &[f64], max_share: f64) -> Vec<f64> {
+    let positive = weights.iter().filter(|&&w| w > 0.0).count();
+    if positive == 0 {
+        return vec![0.0; weights.len()];
+    }
+    if positive as f64 * max_share <= 1.0 {
+        return weights.iter().map(|&w| if w > 0.0 { 1.0 / positive as f64 } else { 0.0 }).collect();
+    }
+
+    let mut capped = vec![false; weights.len()];
+    loop {
+        let capped_count = capped.iter().filter(|&&c| c).count();
+        let remaining_share = 1.0 - capped_count as f64 * max_share;
+        let uncapped_weight: f64 = weights.iter().zip(&capped).filter(|(_, &c)| !c).map(|(w, _)| w).sum();
+
+        let shares: Vec<f64> = weights.iter().zip(&capped)
+            .map(|(&w, &c)| if c { max_share } else { remaining_share * w / uncapped_weight })
+            .collect();
+
+        let mut changed = false;
+        for (i, &share) in shares.iter().enumerate() {
+            if !capped[i] && share > max_share {
+                capped[i] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return shares;
+        }
+    }
+}
+
+// Reorders a sampled list so the first picks all have distinct delegation
+// addresses; repeats of an address follow once every address is used.
+// Validators without an address count as their own provider.
+fn spread_by_provider(picked: Vec<usize>, validators: &[&Validator]) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    let (first, repeats): (Vec<usize>, Vec<usize>) = picked.into_iter().partition(|&i| {
+        match &validators[i].delegation_address {
+            Some(address) => seen.insert(address.to_lowercase()),
+            None => true,
+        }
+    });
+    first.into_iter().chain(repeats).collect()
+}
+
+// Splits `total` indivisible units proportionally to `weights`, flooring each
+// share. Whatever rounding leaves over goes to the highest weight, so the
+// shares always sum to `total`. All-zero weights split evenly.
+fn allocate_units(total: u64, weights: &[f64]) -> Vec<u64> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let weight_sum: f64 = weights.iter().sum();
+    let mut shares: Vec<u64> = if weight_sum > 0.0 {
+        weights.iter().map(|w| (total as f64 * w / weight_sum).floor() as u64).collect()
+    } else {
+        vec![total / weights.len() as u64; weights.len()]
+    };
+
+    let allocated: u64 = shares.iter().sum();
+    let top = weights.iter()
+        .enumerate()
+        .fold(0, |best, (i, w)| if *w > weights[best] { i } else { best });
+    // Also absorbs the odd unit float error might over-allocate
+    shares[top] = (shares[top] + total).saturating_sub(allocated);
+    shares
+}
+
+// Every pick needs a delegation address to be delegated to. `amount_units`
+// are the allocate_units() shares in units of 10^-precision.
+fn delegation_tx(
+    seed: u64,
+    picks: &[&Validator],
+    weights: &[f64],
+    amount_units: Option<&[u64]>,
+    precision: u32,
+) -> Result<DelegationTx, String> {
+    let missing: Vec<u32> = picks.iter().filter(|v| v.delegation_address.is_none()).map(|v| v.id).collect();
+    if !missing.is_empty() {
+        return Err(format!("format=tx needs a delegation address for every pick, validators {:?} have none", missing));
+    }
+
+    let wei_per_unit = 10u128.pow(WEI_DECIMALS - precision);
+    Ok(DelegationTx {
+        seed,
+        method: "batchDelegate".to_string(),
+        ids: picks.iter().map(|v| v.id).collect(),
+        delegatees: picks.iter().filter_map(|v| v.delegation_address.clone()).collect(),
+        bips: allocate_units(DELEGATION_BIPS, weights),
+        amounts_wei: amount_units.map(|units| {
+            units.iter().map(|&u| (u128::from(u) * wei_per_unit).to_string()).collect()
+        }),
+    })
+}
+
+// Compares a held split with a recommended one, both in whole units of
+// 1 / `unit` summing to the same total. Moves pair the most over-allocated
+// validators with the most under-allocated ones until every difference is settled.
+fn selection_drift(data: &ValidatorResponse, held: &[(u32, u64)], recommended: &[(u32, u64)], unit: f64) -> DriftResponse {
+    let status = |id: u32| {
+        if let Some(v) = data.eligible_nodes.iter().find(|v| v.id == id) {
+            ("eligible", Some(v.name.clone()))
+        } else if let Some(v) = data.ineligible_nodes.iter().find(|v| v.id == id) {
+            ("ineligible", Some(v.name.clone()))
+        } else {
+            ("unknown", None)
+        }
+    };
+
+    let mut ids: Vec<u32> = held.iter().map(|&(id, _)| id).collect();
+    ids.extend(recommended.iter().map(|&(id, _)| id).filter(|id| !held.iter().any(|(h, _)| h == id)));
+    let units_of = |split: &[(u32, u64)], id: u32| split.iter().find(|(s, _)| *s == id).map_or(0, |&(_, u)| u);
+
+    let mut positions = Vec::with_capacity(ids.len());
+    let mut deltas: Vec<(u32, i128)> = Vec::with_capacity(ids.len());
+    let (mut now_ineligible, mut unknown_ids) = (Vec::new(), Vec::new());
+    for &id in &ids {
+        let (current, target) = (units_of(held, id), units_of(recommended, id));
+        let (status, name) = status(id);
+        match status {
+            "ineligible" => now_ineligible.push(id),
+            "unknown" => unknown_ids.push(id),
+            _ => {},
+        }
+        positions.push(DriftPosition {
+            id,
+            name,
+            status: status.to_string(),
+            current: current as f64 / unit,
+            recommended: target as f64 / unit,
+            difference: (target as f64 - current as f64) / unit,
+        });
+        deltas.push((id, i128::from(target) - i128::from(current)));
+    }
+
+    let by_size = |a: &(u32, i128), b: &(u32, i128)| b.1.abs().cmp(&a.1.abs()).then(a.0.cmp(&b.0));
+    let mut over: Vec<(u32, i128)> = deltas.iter().copied().filter(|(_, d)| *d < 0).collect();
+    let mut under: Vec<(u32, i128)> = deltas.iter().copied().filter(|(_, d)| *d > 0).collect();
+    over.sort_by(by_size);
+    under.sort_by(by_size);
+
+    let mut moves = Vec::new();
+    let (mut o, mut u) = (0, 0);
+    while o < over.len() && u < under.len() {
+        let amount = (-over[o].1).min(under[u].1);
+        moves.push(RebalanceMove { from: over[o].0, to: under[u].0, amount: amount as f64 / unit });
+        over[o].1 += amount;
+        under[u].1 -= amount;
+        if over[o].1 == 0 {
+            o += 1;
+        }
+        if under[u].1 == 0 {
+            u += 1;
+        }
+    }
+
+    let total: u64 = held.iter().map(|&(_, units)| units).sum();
+    let moved: i128 = deltas.iter().map(|(_, d)| d.abs()).sum::<i128>() / 2;
+    DriftResponse {
+        timestamp: data.timestamp.clone(),
+        total_amount: total as f64 / unit,
+        drift_amount: moved as f64 / unit,
+        drift_share: if total > 0 { moved as f64 / total as f64 } else { 0.0 },
+        now_ineligible,
+        unknown_ids,
+        positions,
+        moves,
+    }
+}
+
+fn summarize(mut values: Vec<f64>) -> SummaryStats {
+    if values.is_empty() {
+        return SummaryStats::default();
+    }
+    values.sort_by(f64::total_cmp);
+
+    let count = values.len();
+    let mean = values.iter().sum::<f64>() / count as f64;
+    // Population standard deviation: the eligible set is the whole population
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+
+    SummaryStats {
+        count,
+        mean: Some(mean),
+        median: median(&values),
+        min: values.first().copied(),
+        max: values.last().copied(),
+        stddev: Some(variance.sqrt()),
+    }
+}
+
+// `excluded` only narrows the rate and availability summaries; counts stay raw
+fn compute_stats(data: &ValidatorResponse, excluded: &[u32]) -> StatsResponse {
+    let summarized = || data.eligible_nodes.iter().filter(|v| !excluded.contains(&v.id));
+    let combined = summarized()
+        .filter_map(|v| v.reward_rates.as_ref().map(|r| r.combined))
+        .collect();
+    let availability = summarized()
+        .filter_map(|v| v.provider_stats.as_ref().and_then(|p| p.availability))
+        .collect();
+
+    let mut condition_counts = ConditionCounts::default();
+    let mut passes_distribution: std::collections::BTreeMap<u8, usize> = (0..=4).map(|p| (p, 0)).collect();
+    for c in data.eligible_nodes.iter().chain(&data.ineligible_nodes).filter_map(|v| v.conditions.as_ref()) {
+        condition_counts.ftso_anchor_feeds += c.ftso_anchor_feeds as usize;
+        condition_counts.ftso_block_latency_feeds += c.ftso_block_latency_feeds as usize;
+        condition_counts.fdc += c.fdc as usize;
+        condition_counts.staking += c.staking as usize;
+        condition_counts.eligible_for_reward += c.eligible_for_reward as usize;
+        *passes_distribution.entry(c.passes).or_default() += 1;
+    }
+
+    StatsResponse {
+        timestamp: data.timestamp.clone(),
+        total_validators: data.total_validators,
+        eligible_count: data.eligible_count,
+        combined_rate: summarize(combined),
+        availability: summarize(availability),
+        condition_counts,
+        passes_distribution,
+        excluded_outliers: excluded.to_vec(),
+    }
+}
+
+// Linear interpolation between the closest ranks of an ascending list
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+// Ids of validators whose combined rate lies more than OUTLIER_IQR_FACTOR
+// interquartile ranges outside the middle half of the set, or is not a finite
+// number at all. Nothing is flagged without MIN_OUTLIER_SAMPLE finite rates or
+// when they have no spread.
+fn rate_outliers(validators: &[Validator]) -> Vec<u32> {
+    let mut rates: Vec<f64> = validators.iter()
+        .filter_map(|v| v.reward_rates.as_ref().map(|r| r.combined))
+        .filter(|r| r.is_finite())
+        .collect();
+    if rates.len() < MIN_OUTLIER_SAMPLE {
+        return Vec::new();
+    }
+    rates.sort_by(f64::total_cmp);
+    let (q1, q3) = (quantile(&rates, 0.25), quantile(&rates, 0.75));
+    let iqr = q3 - q1;
+    if iqr <= 0.0 {
+        return Vec::new();
+    }
+    let fence = (q1 - OUTLIER_IQR_FACTOR * iqr)..=(q3 + OUTLIER_IQR_FACTOR * iqr);
+    validators.iter()
+        .filter(|v| v.reward_rates.as_ref().is_some_and(|r| !fence.contains(&r.combined)))
+        .map(|v| v.id)
+        .collect()
+}
+
+fn check_invariants(data: &ValidatorResponse, criteria: &EligibilityCriteria) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    if data.eligible_count != data.eligible_nodes.len() {
+        violations.push(format!(
+            "eligible_count is {} but {} eligible nodes are listed",
+            data.eligible_count, data.eligible_nodes.len()
+        ));
+    }
+    if data.ineligible_count != data.ineligible_nodes.len() {
+        violations.push(format!(
+            "ineligible_count is {} but {} ineligible nodes are listed",
+            data.ineligible_count, data.ineligible_nodes.len()
+        ));
+    }
+    if data.eligible_count + data.ineligible_count != data.total_validators {
+        violations.push(format!(
+            "eligible_count + ineligible_count = {} but total_validators is {}",
+            data.eligible_count + data.ineligible_count, data.total_validators
+        ));
+    }
+
+    for v in data.eligible_nodes.iter().filter(|v| !is_eligible(v, criteria)) {
+        violations.push(format!("validator {} is listed as eligible but fails the criteria", v.id));
+    }
+    for v in data.ineligible_nodes.iter().filter(|v| is_eligible(v, criteria)) {
+        violations.push(format!("validator {} is listed as ineligible but meets the criteria", v.id));
+    }
+
+    let mut seen = HashSet::new();
+    for v in data.eligible_nodes.iter().chain(data.ineligible_nodes.iter()) {
+        if !seen.insert(v.id) {
+            violations.push(format!("validator id {} appears more than once", v.id));
+        }
+    }
+
+    // Ranks are positions in the eligible list, so they are contiguous as
+    // long as the list is ordered by combined reward rate
+    for (i, pair) in data.eligible_nodes.windows(2).enumerate() {
+        if combined_rate(&pair[0]) < combined_rate(&pair[1]) {
+            violations.push(format!(
+                "eligible ranks {} and {} are out of order (validators {} and {})",
+                i + 1, i + 2, pair[0].id, pair[1].id
+            ));
+        }
+    }
+
+    violations
+}
+
+// Averages each validator's combined rate over the current value and the
+// previous window - 1 snapshots; validators without a full window of samples
+// keep the instantaneous value and are flagged with combined_avg_fallback
+fn apply_trailing_averages(validators: &mut [Validator], history: &VecDeque<Snapshot>, window: usize) {
+    let previous: Vec<HashMap<u32, f64>> = history.iter()
+        .rev()
+        .take(window.saturating_sub(1))
+        .map(|snapshot| snapshot.entries.iter()
+            .filter_map(|e| e.combined.map(|c| (e.id, c)))
+            .collect())
+        .collect();
+
+    for validator in validators {
+        let id = validator.id;
+        let Some(rates) = &mut validator.reward_rates else { continue };
+
+        let samples: Vec<f64> = std::iter::once(rates.combined)
+            .chain(previous.iter().map_while(|snapshot| snapshot.get(&id).copied()))
+            .collect();
+        if samples.len() >= window {
+            rates.combined_avg = samples.iter().sum::<f64>() / samples.len() as f64;
+            rates.combined_avg_fallback = false;
+        }
+    }
+}
+
+fn record_snapshot(state: &AppState, response: &ValidatorResponse) {
+    // Stamped with the data's own timestamp, so a client that passes back the
+    // timestamp it was served finds exactly the snapshot it saw
+    let timestamp = DateTime::parse_from_rfc3339(&response.timestamp)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now());
+    let snapshot = Snapshot {
+        timestamp,
+        entries: snapshot_entries(response),
+    };
+
+    let mut history = state.history.write();
+    if history.len() >= HISTORY_SIZE {
+        history.pop_front();
+    }
+    history.push_back(snapshot);
+}
+
+fn snapshot_entries(response: &ValidatorResponse) -> Vec<SnapshotEntry> {
+    let entry = |v: &Validator, rank| {
+        let rates = v.reward_rates.as_ref();
+        SnapshotEntry {
+            id: v.id,
+            rank,
+            wnat: rates.map(|r| r.wnat),
+            mirror: rates.map(|r| r.mirror),
+            pure: rates.map(|r| r.pure),
+            combined: rates.map(|r| r.combined),
+            active: v.provider_stats.as_ref().and_then(|p| p.active),
+        }
+    };
+    let eligible = response.eligible_nodes.iter()
+        .enumerate()
+        .map(|(i, v)| entry(v, Some(i + 1)));
+    let ineligible = response.ineligible_nodes.iter()
+        .map(|v| entry(v, None));
+
+    eligible.chain(ineligible).collect()
+}
+
+// Oldest first. Refreshes the validator was absent from produce no sample;
+// the next sample is flagged and its delta spans the gap.
+fn rate_history(history: &VecDeque<Snapshot>, id: u32) -> Vec<RateSample> {
+    let mut samples: Vec<RateSample> = Vec::new();
+    let mut previous_combined = None;
+    let mut missed = false;
+
+    for snapshot in history {
+        let Some(entry) = snapshot.entries.iter().find(|e| e.id == id) else {
+            missed |= !samples.is_empty();
+            continue;
+        };
+        samples.push(RateSample {
+            timestamp: snapshot.timestamp.to_rfc3339(),
+            rank: entry.rank,
+            wnat: entry.wnat,
+            mirror: entry.mirror,
+            pure: entry.pure,
+            combined: entry.combined,
+            combined_delta: entry.combined.zip(previous_combined).map(|(now, before)| now - before),
+            gap_before: std::mem::take(&mut missed),
+        });
+        previous_combined = entry.combined;
+    }
+
+    samples
+}
+
+// Latest snapshot taken at or before the given time
+fn snapshot_at(history: &VecDeque<Snapshot>, at: DateTime<Utc>) -> Option<&Snapshot> {
+    history.iter().rev().find(|s| s.timestamp <= at)
+}
+
+fn diff_snapshots(from: &Snapshot, to: &Snapshot) -> DiffRangeResponse {
+    let from_ranks: HashMap<u32, Option<usize>> = from.entries.iter().map(|e| (e.id, e.rank)).collect();
+    let to_ranks: HashMap<u32, Option<usize>> = to.entries.iter().map(|e| (e.id, e.rank)).collect();
+
+    let mut became_eligible = Vec::new();
+    let mut rank_changes = Vec::new();
+    for entry in &to.entries {
+        let Some(to_rank) = entry.rank else { continue };
+        match from_ranks.get(&entry.id).copied().flatten() {
+            Some(from_rank) if from_rank != to_rank => rank_changes.push(RankChange {
+                id: entry.id,
+                from_rank,
+                to_rank,
+            }),
+            Some(_) => {}
+            None => became_eligible.push(entry.id),
+        }
+    }
+
+    let became_ineligible = from.entries.iter()
+        .filter(|e| e.rank.is_some() && to_ranks.get(&e.id).copied().flatten().is_none())
+        .map(|e| e.id)
+        .collect();
+
+    DiffRangeResponse {
+        from: from.timestamp.to_rfc3339(),
+        to: to.timestamp.to_rfc3339(),
+        became_eligible,
+        became_ineligible,
+        rank_changes,
+    }
+}
+
+// What changed in the current data since a baseline snapshot: validators whose
+// combined rate, eligibility or active flag differ (eligible first, in rank
+// order), then the ids that appeared and disappeared
+fn changes_since(baseline: &Snapshot, data: &ValidatorResponse) -> (Vec<ChangedValidator>, Vec<u32>, Vec<u32>) {
+    let before: HashMap<u32, &SnapshotEntry> = baseline.entries.iter().map(|e| (e.id, e)).collect();
+    let mut current = HashSet::new();
+    let mut changed = Vec::new();
+    let mut added = Vec::new();
+
+    let validators = data.eligible_nodes.iter().map(|v| (v, true))
+        .chain(data.ineligible_nodes.iter().map(|v| (v, false)));
+    for (v, eligible) in validators {
+        current.insert(v.id);
+        let Some(entry) = before.get(&v.id) else {
+            added.push(v.id);
+            continue;
+        };
+        let fields: Vec<String> = [
+            ("combined", entry.combined != v.reward_rates.as_ref().map(|r| r.combined)),
+            ("eligible", entry.rank.is_some() != eligible),
+            ("active", entry.active != v.provider_stats.as_ref().and_then(|p| p.active)),
+        ]
+        .into_iter()
+        .filter(|&(_, differs)| differs)
+        .map(|(field, _)| field.to_string())
+        .collect();
+        if !fields.is_empty() {
+            changed.push(ChangedValidator { validator: v.clone(), eligible, changed: fields });
+        }
+    }
+
+    let removed = baseline.entries.iter()
+        .map(|e| e.id)
+        .filter(|id| !current.contains(id))
+        .collect();
+
+    (changed, added, removed)
+}
+
+// Converts a per-epoch rate to an annual percentage yield, as a fraction like
+// the rate, assuming rewards are compounded every epoch:
+// apy = (1 + rate)^epochs_per_year - 1
+fn annualize(rate: f64, epochs_per_year: f64) -> f64 {
+    (1.0 + rate).powf(epochs_per_year) - 1.0
+}
+
+// Converts a raw upstream availability into a fraction in [0, 1], where
+// `scale` is the raw value meaning 100%. Out-of-range input is clamped and
+// logged, since it most likely means Flare changed the format.
+fn scale_availability(raw: f64, scale: f64, entity_id: u32) -> f64 {
+    if !(0.0..=scale).contains(&raw) {
+        log::warn!(
+            "Entity {} reported availability {} outside the expected 0..={} range, clamping",
+            entity_id, raw, scale
+        );
+    }
+    (raw / scale).clamp(0.0, 1.0)
+}
+
+fn process_entity(entity: &FlareEntity, epochs_per_year: f64, availability_scale: f64) -> Validator {
+    // Extract conditions
+    let conditions = entity.entityminimalconditions.as_ref().map(|c| Conditions {
+        ftso_anchor_feeds: c.ftso_scaling.unwrap_or(false),
+        ftso_block_latency_feeds: c.ftso_fast_updates.unwrap_or(false),
+        fdc: c.fdc.unwrap_or(false),
+        staking: c.staking.unwrap_or(false),
+        passes: c.passes_held.unwrap_or(0),
+        passes_held: c.passes_held,
+        eligible_for_reward: c.eligible_for_reward.unwrap_or(false),
+    });
+
+    // Extract reward rates
+    let reward_rates = entity.rewards.as_ref().map(|r| {
+        let wnat = r.reward_rate_wnat.unwrap_or(0.0);
+        let mirror = r.reward_rate_mirror.unwrap_or(0.0);
+        let pure = r.reward_rate_pure.unwrap_or(0.0);
+        let combined = wnat + mirror + pure;
+
+        RewardRates {
+            wnat,
+            mirror,
+            pure,
+            combined,
+            apy: annualize(combined, epochs_per_year),
+            combined_avg: combined,
+            combined_avg_fallback: true,
+            normalized_combined: None,
+            units: RateUnits::new(epochs_per_year),
+        }
+    });
+
+    // Extract provider stats
+    let provider_stats = entity.providersuccessrate.as_ref().map(|p| ProviderStats {
+        primary: p.primary,
+        secondary: p.secondary,
+        availability: p.availability.map(|a| scale_availability(a, availability_scale, entity.id)),
+        active: p.active,
+    });
+
+    let denormalized = entity.denormalizedentity.as_ref();
+    let node_ids = denormalized.map(|d| d.node_ids.clone()).unwrap_or_default();
+
+    Validator {
         id: entity.id,
         name: entity.display_name.clone().unwrap_or_else(|| "Unknown".to_string()),
-        node_id: entity.denormalizedentity.as_ref().and_then(|d| d.node_ids.first().cloned()),
+        node_id: node_ids.first().cloned(),
+        node_ids,
         delegation_address: entity.denormalizedsigningpolicy.as_ref().and_then(|d| d.delegation_address.clone()),
         conditions,
         provider_stats,
+        estimated_apy: reward_rates.as_ref().map(|r| annualize(r.combined, epochs_per_year)),
         reward_rates,
+        rewards_signed: denormalized.and_then(|d| d.rewards_signed),
+        uptime_signed: denormalized.and_then(|d| d.uptime_signed),
+        rate_vs_median: None,
+        unmet_criteria: None,
+        ineligibility_reasons: None,
+        merged_ids: Vec::new(),
+    }
+}
+
+fn cache_age_secs(state: &AppState) -> Option<u64> {
+    let cache_read = state.cache.read();
+    cache_read.as_ref().map(|cached| {
+        SystemTime::now().duration_since(cached.fetched_at).unwrap_or_default().as_secs()
+    })
+}
+
+fn query_param(req: &ServiceRequest, key: &str) -> Option<String> {
+    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|q| q.get(key).cloned())
+}
+
+fn is_json_response<B>(res: &ServiceResponse<B>) -> bool {
+    res.headers()
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"))
+}
+
+// Buffers a JSON response so middleware can rewrite it
+async fn take_json_body<B: MessageBody>(
+    res: ServiceResponse<B>,
+) -> Result<(actix_web::HttpRequest, HttpResponse<()>, serde_json::Value), actix_web::Error> {
+    let (req, res) = res.into_parts();
+    let (head, body) = res.into_parts();
+    let bytes = to_bytes(body).await
+        .map_err(|e| actix_web::error::ErrorInternalServerError(e.into().to_string()))?;
+    let body = serde_json::from_slice(&bytes)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok((req, head, body))
+}
+
+fn json_service_response<T: Serialize>(
+    req: actix_web::HttpRequest,
+    head: HttpResponse<()>,
+    body: &T,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let body = serde_json::to_vec(body).map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(ServiceResponse::new(req, head.set_body(body).map_into_boxed_body()))
+}
+
+// Wraps JSON bodies in {data, meta, errors} when RESPONSE_ENVELOPE=wrapped or
+// the request asks for ?envelope=wrapped, leaving handlers unaware of it
+async fn response_envelope(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let state = req.app_data::<web::Data<Arc<AppState>>>().cloned();
+    // The /api/v2 routes build their own typed envelope
+    let wrapped = !req.path().starts_with("/api/v2/") && match query_param(&req, "envelope").as_deref() {
+        Some("wrapped") => true,
+        Some("raw") => false,
+        _ => state.as_ref().is_some_and(|s| s.wrap_responses),
+    };
+
+    let res = next.call(req).await?;
+    if !wrapped || !is_json_response(&res) {
+        return Ok(res.map_into_boxed_body());
+    }
+
+    let (req, head, mut body) = take_json_body(res).await?;
+
+    let pagination = body.as_object_mut()
+        .and_then(|o| o.remove("pagination"))
+        .and_then(|p| serde_json::from_value(p).ok());
+    let data_timestamp = body.get("data_timestamp").and_then(|t| t.as_str()).map(str::to_string);
+    let cache_status = head.headers().get("X-Cache-Status")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let (data, errors) = if head.status().is_success() {
+        (Some(body), Vec::new())
+    } else {
+        let message = body.get("error")
+            .and_then(|e| e.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| body.to_string());
+        (None, vec![message])
+    };
+
+    let envelope = ApiEnvelope {
+        data,
+        meta: EnvelopeMeta {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data_timestamp,
+            cache_age_secs: state.as_ref().and_then(|s| cache_age_secs(s)),
+            cache_status,
+            pagination,
+        },
+        errors,
+    };
+
+    json_service_response(req, head, &envelope)
+}
+
+// Echoes the id TracingLogger put on the request span, so a client report can
+// be matched to its log lines
+async fn request_id_header(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    use actix_web::HttpMessage;
+
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.to_string());
+    let mut res = next.call(req).await?;
+    if let Some(value) = request_id.and_then(|id| actix_web::http::header::HeaderValue::from_str(&id).ok()) {
+        res.headers_mut().insert(actix_web::http::header::HeaderName::from_static("x-request-id"), value);
+    }
+    Ok(res)
+}
+
+// Probes must keep working however busy a client is
+const RATE_LIMIT_EXEMPT: &[&str] = &["/health", "/ready"];
+
+async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let state = req.app_data::<web::Data<Arc<AppState>>>().cloned();
+    let limiter = state.as_ref().and_then(|s| s.rate_limiter.as_ref());
+    let Some(limiter) = limiter.filter(|_| !RATE_LIMIT_EXEMPT.contains(&req.path())) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+
+    if let Some(ip) = client_addr(&req).ip {
+        if let Err(wait) = limiter.check(ip, Instant::now()) {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            log::debug!("Rate limited {} on {}, retry in {}s", ip, req.path(), retry_after);
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((actix_web::http::header::RETRY_AFTER, retry_after.to_string()))
+                .json(serde_json::json!({ "error": "Too many requests, try again later" }));
+            return Ok(req.into_response(response));
+        }
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+// Counts every request by its route pattern so ids in paths don't explode the label set
+async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let state = req.app_data::<web::Data<Arc<AppState>>>().cloned();
+    let method = req.method().to_string();
+
+    let res = next.call(req).await?;
+    if let Some(state) = state {
+        let endpoint = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+        state.metrics.record_request(&method, &endpoint);
+    }
+    Ok(res)
+}
+
+fn parse_field_aliases(spec: &str) -> HashMap<String, String> {
+    spec.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(from, to)| (from.trim().to_string(), to.trim().to_string()))
+        .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+        .collect()
+}
+
+fn rename_fields(value: &mut serde_json::Value, aliases: &HashMap<String, String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let renamed = std::mem::take(map).into_iter()
+                .map(|(key, mut v)| {
+                    rename_fields(&mut v, aliases);
+                    (aliases.get(&key).cloned().unwrap_or(key), v)
+                })
+                .collect();
+            *map = renamed;
+        },
+        serde_json::Value::Array(items) => {
+            for item in items {
+                rename_fields(item, aliases);
+            }
+        },
+        _ => {},
+    }
+}
+
+// Renames output fields per FIELD_ALIASES when the request asks for
+// ?profile=aliased; the structs and default field names stay untouched
+async fn field_aliases(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let state = req.app_data::<web::Data<Arc<AppState>>>().cloned();
+    let aliased = query_param(&req, "profile").as_deref() == Some("aliased");
+
+    let res = next.call(req).await?;
+    let aliases = state.as_ref().map(|s| &s.field_aliases).filter(|a| !a.is_empty());
+    let Some(aliases) = aliases.filter(|_| aliased && is_json_response(&res)) else {
+        return Ok(res.map_into_boxed_body());
+    };
+
+    let (req, head, mut body) = take_json_body(res).await?;
+    rename_fields(&mut body, aliases);
+
+    json_service_response(req, head, &body)
+}
+
+// Documents the default response shapes and the typed /api/v2 envelopes;
+// ?envelope=wrapped and ?profile=aliased reshape bodies in middleware and are
+// described in the README instead
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Flare Validator API", version = "1.0.0"),
+    paths(
+        usage,
+        health_check,
+        readiness_check,
+        deep_health_check,
+        get_all_validators,
+        stream_validators,
+        subscribe_validators,
+        get_eligible_validators,
+        get_ineligible_validators,
+        get_top_validators,
+        get_all_validators_v2,
+        get_eligible_validators_v2,
+        get_ineligible_validators_v2,
+        get_top_validators_v2,
+        select_validators,
+        random_validator,
+        compare_validators,
+        search_validators,
+        get_diff_range,
+        get_changes,
+        get_validator_by_node,
+        get_validator_by_delegation,
+        get_validator_by_id,
+        get_validator_history,
+        get_delegation_url,
+        get_nodes_status,
+        batch_validators,
+        selection_drift_advice,
+        get_stats,
+        get_epoch,
+        get_cache_status,
+        selftest,
+        diagnostics,
+        upstream_ping,
+        metrics,
+        validator_metrics,
+        force_refresh,
+        openapi_spec,
+    )
+)]
+struct ApiDoc;
+
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "This OpenAPI document", body = Object))
+)]
+#[get("/openapi.json")]
+async fn openapi_spec() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "Endpoint list and a link to this spec", body = UsageResponse))
+)]
+#[get("/")]
+async fn usage(state: web::Data<Arc<AppState>>) -> impl Responder {
+    HttpResponse::Ok().json(UsageResponse {
+        api_name: "Flare Validator API".to_string(),
+        version: "1.0.0".to_string(),
+        network: state.network.name().to_string(),
+        openapi: "/openapi.json".to_string(),
+        endpoints: vec![
+            "/health".to_string(),
+            "/ready".to_string(),
+            "/health/deep".to_string(),
+            "/api/validators".to_string(),
+            "/api/validators/stream".to_string(),
+            "/api/validators/subscribe".to_string(),
+            "/api/validators/eligible".to_string(),
+            "/api/validators/ineligible".to_string(),
+            "/api/validators/top?limit=N&offset=M".to_string(),
+            "/api/v2/validators".to_string(),
+            "/api/v2/validators/eligible".to_string(),
+            "/api/v2/validators/ineligible".to_string(),
+            "/api/v2/validators/top".to_string(),
+            "/api/validators/select?count=N&seed=S".to_string(),
+            "/api/validators/random?seed=S".to_string(),
+            "/api/validators/compare?ids=1,2,3".to_string(),
+            "/api/validators/search?q=NAME".to_string(),
+            "/api/validators/diff-range?from=TS&to=TS".to_string(),
+            "/api/validators/changes?since=TS".to_string(),
+            "/api/validators/by-node/{node_id}".to_string(),
+            "/api/validators/by-delegation/{address}".to_string(),
+            "/api/validators/{id}".to_string(),
+            "/api/validators/{id}/history".to_string(),
+            "/api/validators/{id}/delegation-url".to_string(),
+            "/api/nodes/status".to_string(),
+            "/api/validators/batch".to_string(),
+            "/api/select/drift".to_string(),
+            "/api/stats".to_string(),
+            "/api/epoch".to_string(),
+            "/api/cache/status".to_string(),
+            "/api/selftest".to_string(),
+            "/api/diagnostics".to_string(),
+            "/api/upstream/ping".to_string(),
+            "/metrics".to_string(),
+            "/metrics/validators".to_string(),
+            "/api/refresh".to_string(),
+            "/openapi.json".to_string(),
+        ],
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "The process is up", body = HealthResponse))
+)]
+#[get("/health")]
+async fn health_check() -> impl Responder {
+    HttpResponse::Ok().json(HealthResponse {
+        status: "ok".to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+// Readiness, unlike /health: 503 until there is cached data to serve
+#[utoipa::path(
+    tag = "service",
+    responses(
+        (status = 200, description = "Cached data is available", body = HealthResponse),
+        (status = 503, description = "No data cached yet", body = HealthResponse),
+    )
+)]
+#[get("/ready")]
+async fn readiness_check(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let ready = state.ready.load(Ordering::Acquire);
+    let body = HealthResponse {
+        status: if ready { "ready" } else { "not ready" }.to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    };
+
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+// Reports rather than fails: always 200, with "degraded" on the subsystems in
+// trouble, so a slow upstream shows up on dashboards without paging as an outage
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "Upstream and cache status", body = DeepHealthResponse))
+)]
+#[get("/health/deep")]
+async fn deep_health_check(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let status = |ok: bool| if ok { "ok" } else { "degraded" }.to_string();
+
+    let probe = probe_upstream(&state, Duration::from_secs(DEEP_HEALTH_TIMEOUT_SECS)).await;
+    let upstream = UpstreamHealth { status: status(probe.reachable), probe };
+
+    let fetched_at = state.cache.read().as_ref().map(|cached| cached.fetched_at);
+    let age = fetched_at.map(|at| SystemTime::now().duration_since(at).unwrap_or_default());
+    let expired = age.is_none_or(|age| age >= state.cache_ttl);
+    let last_refresh_failed = state.last_refresh_failed.load(Ordering::Acquire);
+    let cache = CacheHealth {
+        status: status(!expired && !last_refresh_failed),
+        populated: fetched_at.is_some(),
+        age_secs: age.map(|age| age.as_secs()),
+        expired,
+        last_refresh_failed,
+    };
+
+    HttpResponse::Ok().json(DeepHealthResponse {
+        status: status(upstream.status == "ok" && cache.status == "ok"),
+        upstream,
+        cache,
+        subscribers: SubscriberHealth {
+            open: state.sse_subscribers.count(),
+            max: state.sse_subscribers.max,
+        },
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(ListPageParams, ListFilterParams),
+    responses(
+        (status = 200, description = "Eligible then ineligible validators, one page at a time", body = Timestamped<Paginated<ValidatorResponse>>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators")]
+async fn get_all_validators(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_all_validators(&req, &state, &query).await;
+    render_list(ListShape::Flat, cache_age_secs(&state), result)
+}
+
+async fn list_all_validators(
+    req: &actix_web::HttpRequest,
+    state: &Arc<AppState>,
+    query: &HashMap<String, String>,
+) -> Result<ListOutcome<ValidatorResponse>, ListError> {
+    let format = ResponseFormat::negotiate(req, query).map_err(ListError::NotAcceptable)?;
+    let ignore = parse_ignore(query).map_err(ListError::BadRequest)?;
+    let filter = ValidatorFilter::from_query(query).map_err(ListError::BadRequest)?;
+    let sort = ListSort::from_query(query).map_err(ListError::BadRequest)?;
+    let page = PageRequest::from_query(query).map_err(ListError::BadRequest)?;
+    let fields = FieldMask::from_query(query).map_err(ListError::BadRequest)?;
+
+    let (data, cache_status) = fetch_validator_data(state).await?;
+    let etag = list_etag(cached_content_hash(state, &data), req);
+    if let Some(response) = not_modified(req, &etag, cache_status) {
+        return Ok(ListOutcome::Rendered(response));
+    }
+    let data = resplit(data, &ignore, &state.criteria);
+    let mut eligible = filter.apply(&data.eligible_nodes);
+    let mut ineligible = filter.apply(&data.ineligible_nodes);
+    if let Some(sort) = sort {
+        sort.apply(&mut eligible);
+        sort.apply(&mut ineligible);
+    }
+    // Pages run over eligible then ineligible, so a page can span both lists
+    let total = eligible.len() + ineligible.len();
+    let pagination = page.over(total).map_err(ListError::BadRequest)?;
+    let eligible_page = page.slice(&eligible);
+    let ineligible_page = PageRequest {
+        offset: page.offset.saturating_sub(eligible.len()),
+        limit: page.limit - eligible_page.len(),
+    }.slice(&ineligible);
+    if format == ResponseFormat::Csv {
+        let rows: Vec<&Validator> = eligible_page.iter().chain(ineligible_page).copied().collect();
+        return Ok(ListOutcome::Rendered(csv_response(cache_status, etag, &rows)));
+    }
+    let body = ValidatorResponse {
+        timestamp: data.timestamp.clone(),
+        total_validators: total,
+        eligible_count: eligible.len(),
+        ineligible_count: ineligible.len(),
+        eligible_nodes: to_owned_page(eligible_page),
+        ineligible_nodes: to_owned_page(ineligible_page),
+        reward_epoch: data.reward_epoch,
+    };
+
+    Ok(ListOutcome::Page(ListPage {
+        body,
+        fields,
+        format,
+        pagination,
+        data_timestamp: data.timestamp.clone(),
+        cache_status,
+        etag,
+    }))
+}
+
+// One Validator per line, eligible (ranked) first. The stream keeps its own
+// reference to the cached data, so the cache lock is not held while the client
+// reads and a refresh meanwhile does not affect it.
+#[utoipa::path(
+    tag = "validators",
+    responses(
+        (status = 200, description = "One Validator per line, eligible first", body = Validator, content_type = "application/x-ndjson"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/stream")]
+async fn stream_validators(state: web::Data<Arc<AppState>>) -> impl Responder {
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let eligible_len = data.eligible_nodes.len();
+            let lines = (0..eligible_len + data.ineligible_nodes.len())
+                .map(move |i| {
+                    let v = match i.checked_sub(eligible_len) {
+                        None => &data.eligible_nodes[i],
+                        Some(j) => &data.ineligible_nodes[j],
+                    };
+                    let mut line = serde_json::to_vec(v).map_err(actix_web::error::ErrorInternalServerError)?;
+                    line.push(b'\n');
+                    Ok::<_, actix_web::Error>(web::Bytes::from(line))
+                });
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .content_type("application/x-ndjson")
+                .streaming(futures::stream::iter(lines))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+fn sse_event(event: &RefreshEvent) -> Result<web::Bytes, actix_web::Error> {
+    let json = serde_json::to_string(event).map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(web::Bytes::from(format!("event: refresh\ndata: {}\n\n", json)))
+}
+
+// Server-Sent Events: the cached summary on connect, then one event per
+// completed refresh. A comment line every SSE_KEEPALIVE_SECS keeps proxies from
+// timing out the idle connection. At SSE_MAX_SUBSCRIBERS open streams the new
+// subscriber gets 503, or the oldest stream is closed, per SSE_FULL_POLICY.
+#[utoipa::path(
+    tag = "validators",
+    responses(
+        (status = 200, description = "A refresh event per completed refresh", body = RefreshEvent, content_type = "text/event-stream"),
+        (status = 503, description = "SSE_MAX_SUBSCRIBERS streams are open and SSE_FULL_POLICY is reject"),
+    )
+)]
+#[get("/api/validators/subscribe")]
+async fn subscribe_validators(state: web::Data<Arc<AppState>>) -> impl Responder {
+    use tokio::sync::broadcast::error::RecvError;
+
+    let Some(subscription) = state.sse_subscribers.admit() else {
+        return HttpResponse::ServiceUnavailable()
+            .insert_header((actix_web::http::header::RETRY_AFTER, SSE_KEEPALIVE_SECS.to_string()))
+            .json(serde_json::json!({
+                "error": format!("Too many open subscriptions, at most {} are allowed", state.sse_subscribers.max.unwrap_or_default())
+            }));
+    };
+    // Subscribe before reading the cache so a refresh landing in between still arrives
+    let receiver = state.refresh_events.subscribe();
+    let initial = state.cache.read().as_ref().map(|cached| RefreshEvent::from(&*cached.data));
+    let keepalive = Duration::from_secs(SSE_KEEPALIVE_SECS);
+
+    // The subscription rides along in the stream state so dropping the stream releases it
+    let events = futures::stream::unfold((initial, receiver, subscription), move |(initial, mut receiver, subscription)| async move {
+        if let Some(event) = initial {
+            return Some((sse_event(&event), (None, receiver, subscription)));
+        }
+        let next = tokio::select! {
+            _ = subscription.evicted.notified() => {
+                log::debug!("Closing the oldest SSE subscriber to make room");
+                return None;
+            },
+            next = tokio::time::timeout(keepalive, receiver.recv()) => next,
+        };
+        match next {
+            Ok(Ok(event)) => Some((sse_event(&event), (None, receiver, subscription))),
+            // Missed events can't be replayed, so the client reconnects and starts over
+            Ok(Err(RecvError::Lagged(missed))) => {
+                log::debug!("Dropping SSE subscriber that fell {} events behind", missed);
+                None
+            },
+            Ok(Err(RecvError::Closed)) => None,
+            Err(_) => Some((Ok(web::Bytes::from_static(b": keepalive\n\n")), (None, receiver, subscription))),
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+        // Compress would buffer events until enough bytes pile up
+        .insert_header(actix_web::http::header::ContentEncoding::Identity)
+        .streaming(events)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(ListPageParams, ListFilterParams),
+    responses(
+        (status = 200, description = "Eligible validators, ranked by combined rate", body = Timestamped<Paginated<ValidatorsListResponse>>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/eligible")]
+async fn get_eligible_validators(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_eligible_validators(&req, &state, &query).await;
+    render_list(ListShape::Flat, cache_age_secs(&state), result)
+}
+
+async fn list_eligible_validators(
+    req: &actix_web::HttpRequest,
+    state: &Arc<AppState>,
+    query: &HashMap<String, String>,
+) -> Result<ListOutcome<ValidatorsListResponse>, ListError> {
+    let format = ResponseFormat::negotiate(req, query).map_err(ListError::NotAcceptable)?;
+    let ignore = parse_ignore(query).map_err(ListError::BadRequest)?;
+    let filter = ValidatorFilter::from_query(query).map_err(ListError::BadRequest)?;
+    let sort = ListSort::from_query(query).map_err(ListError::BadRequest)?;
+    let page = PageRequest::from_query(query).map_err(ListError::BadRequest)?;
+    let fields = FieldMask::from_query(query).map_err(ListError::BadRequest)?;
+
+    let (data, cache_status) = fetch_validator_data(state).await?;
+    let etag = list_etag(cached_content_hash(state, &data), req);
+    if let Some(response) = not_modified(req, &etag, cache_status) {
+        return Ok(ListOutcome::Rendered(response));
+    }
+    let data = resplit(data, &ignore, &state.criteria);
+    let mut validators = filter.apply(&data.eligible_nodes);
+    let total = validators.len();
+    if let Some(sort) = sort {
+        sort.apply(&mut validators);
+    }
+    let paged = query.contains_key("offset") || query.contains_key("limit");
+    let truncated = apply_list_cap(&mut validators, state.eligible_list_cap, paged);
+    let pagination = page.over(validators.len()).map_err(ListError::BadRequest)?;
+    if format == ResponseFormat::Csv {
+        return Ok(ListOutcome::Rendered(csv_response(cache_status, etag, page.slice(&validators))));
+    }
+    let validators = to_owned_page(page.slice(&validators));
+
+    Ok(ListOutcome::Page(ListPage {
+        body: ValidatorsListResponse {
+            timestamp: data.timestamp.clone(),
+            count: validators.len(),
+            validators,
+            truncated,
+            total_available: truncated.then_some(total),
+        },
+        fields,
+        format,
+        pagination,
+        data_timestamp: data.timestamp.clone(),
+        cache_status,
+        etag,
+    }))
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ListPageParams,
+        ListFilterParams,
+        ("with_reasons" = Option<bool>, Query, description = "List the criteria each validator fails"),
+    ),
+    responses(
+        (status = 200, description = "Validators failing at least one criterion", body = Timestamped<Paginated<ValidatorsListResponse>>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/ineligible")]
+async fn get_ineligible_validators(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_ineligible_validators(&req, &state, &query).await;
+    render_list(ListShape::Flat, cache_age_secs(&state), result)
+}
+
+async fn list_ineligible_validators(
+    req: &actix_web::HttpRequest,
+    state: &Arc<AppState>,
+    query: &HashMap<String, String>,
+) -> Result<ListOutcome<ValidatorsListResponse>, ListError> {
+    let format = ResponseFormat::negotiate(req, query).map_err(ListError::NotAcceptable)?;
+    let ignore = parse_ignore(query).map_err(ListError::BadRequest)?;
+    let filter = ValidatorFilter::from_query(query).map_err(ListError::BadRequest)?;
+    let sort = ListSort::from_query(query).map_err(ListError::BadRequest)?;
+    let page = PageRequest::from_query(query).map_err(ListError::BadRequest)?;
+    let fields = FieldMask::from_query(query).map_err(ListError::BadRequest)?;
+    let with_reasons = match query.get("with_reasons").map(|w| w.parse::<bool>()) {
+        None => false,
+        Some(Ok(with_reasons)) => with_reasons,
+        Some(Err(_)) => return Err(ListError::BadRequest(
+            "'with_reasons' must be 'true' or 'false'".to_string()
+        )),
+    };
+
+    let (data, cache_status) = fetch_validator_data(state).await?;
+    let etag = list_etag(cached_content_hash(state, &data), req);
+    if let Some(response) = not_modified(req, &etag, cache_status) {
+        return Ok(ListOutcome::Rendered(response));
+    }
+    let data = resplit(data, &ignore, &state.criteria);
+    let mut validators = filter.apply(&data.ineligible_nodes);
+    if let Some(sort) = sort {
+        sort.apply(&mut validators);
+    }
+    let pagination = page.over(validators.len()).map_err(ListError::BadRequest)?;
+    if format == ResponseFormat::Csv {
+        return Ok(ListOutcome::Rendered(csv_response(cache_status, etag, page.slice(&validators))));
+    }
+    let mut validators = to_owned_page(page.slice(&validators));
+    if with_reasons {
+        for v in &mut validators {
+            v.ineligibility_reasons = Some(ineligibility_reasons(v, &ignore, &state.criteria));
+        }
+    }
+
+    Ok(ListOutcome::Page(ListPage {
+        body: ValidatorsListResponse {
+            timestamp: data.timestamp.clone(),
+            count: validators.len(),
+            validators,
+            truncated: false,
+            total_available: None,
+        },
+        fields,
+        format,
+        pagination,
+        data_timestamp: data.timestamp.clone(),
+        cache_status,
+        etag,
+    }))
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("limit" = Option<usize>, Query, description = "Validators to return, default 50"),
+        ("offset" = Option<usize>, Query, description = "Ranks to skip"),
+        ListFilterParams,
+    ),
+    responses(
+        (status = 200, description = "Highest ranked eligible validators", body = Timestamped<Paginated<ValidatorsListResponse>>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/top")]
+async fn get_top_validators(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_top_validators(&req, &state, &query).await;
+    render_list(ListShape::Flat, cache_age_secs(&state), result)
+}
+
+async fn list_top_validators(
+    req: &actix_web::HttpRequest,
+    state: &Arc<AppState>,
+    query: &HashMap<String, String>,
+) -> Result<ListOutcome<ValidatorsListResponse>, ListError> {
+    let limit = match query.get("limit").map(|l| l.parse::<usize>()) {
+        None => DEFAULT_TOP_LIMIT,
+        Some(Ok(limit)) if limit > 0 => limit,
+        Some(_) => return Err(ListError::BadRequest(
+            "'limit' must be a positive integer".to_string()
+        )),
+    };
+    let offset = match query.get("offset").map(|o| o.parse::<usize>()) {
+        None => 0,
+        Some(Ok(offset)) => offset,
+        Some(Err(_)) => return Err(ListError::BadRequest(
+            "'offset' must be a non-negative integer".to_string()
+        )),
+    };
+    let format = ResponseFormat::negotiate(req, query).map_err(ListError::NotAcceptable)?;
+    let ignore = parse_ignore(query).map_err(ListError::BadRequest)?;
+    let filter = ValidatorFilter::from_query(query).map_err(ListError::BadRequest)?;
+    let fields = FieldMask::from_query(query).map_err(ListError::BadRequest)?;
+
+    let (data, cache_status) = fetch_validator_data(state).await?;
+    let etag = list_etag(cached_content_hash(state, &data), req);
+    if let Some(response) = not_modified(req, &etag, cache_status) {
+        return Ok(ListOutcome::Rendered(response));
+    }
+    let data = resplit(data, &ignore, &state.criteria);
+    let ranked = filter.apply(&data.eligible_nodes);
+    // Clamped to the eligible pool, which is all a ranked page can hold
+    let page = PageRequest { offset, limit: limit.min(ranked.len().max(1)) };
+    let pagination = page.over(ranked.len()).map_err(ListError::BadRequest)?;
+    if format == ResponseFormat::Csv {
+        return Ok(ListOutcome::Rendered(csv_response(cache_status, etag, page.slice(&ranked))));
+    }
+    let validators = to_owned_page(page.slice(&ranked));
+
+    Ok(ListOutcome::Page(ListPage {
+        body: ValidatorsListResponse {
+            timestamp: data.timestamp.clone(),
+            count: validators.len(),
+            validators,
+            truncated: false,
+            total_available: None,
+        },
+        fields,
+        format,
+        pagination,
+        data_timestamp: data.timestamp.clone(),
+        cache_status,
+        etag,
+    }))
+}
+
+// The /api/v2 list routes take the same parameters as their unversioned
+// counterparts and return the same data inside an ApiEnvelope
+#[utoipa::path(
+    tag = "validators",
+    params(ListPageParams, ListFilterParams),
+    responses(
+        (status = 200, description = "Eligible then ineligible validators, one page at a time", body = ApiEnvelope<ValidatorResponse>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter", body = ApiEnvelope<ValidatorResponse>),
+        (status = 406, description = "Requested format cannot be produced", body = ApiEnvelope<ValidatorResponse>),
+        (status = 502, description = "Upstream sent a response that could not be used", body = ApiEnvelope<ValidatorResponse>),
+        (status = 503, description = "Upstream unreachable and nothing cached", body = ApiEnvelope<ValidatorResponse>),
+    )
+)]
+#[get("/api/v2/validators")]
+async fn get_all_validators_v2(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_all_validators(&req, &state, &query).await;
+    render_list(ListShape::Enveloped, cache_age_secs(&state), result)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(ListPageParams, ListFilterParams),
+    responses(
+        (status = 200, description = "Eligible validators, ranked by combined rate", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 406, description = "Requested format cannot be produced", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 502, description = "Upstream sent a response that could not be used", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 503, description = "Upstream unreachable and nothing cached", body = ApiEnvelope<ValidatorsListResponse>),
+    )
+)]
+#[get("/api/v2/validators/eligible")]
+async fn get_eligible_validators_v2(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_eligible_validators(&req, &state, &query).await;
+    render_list(ListShape::Enveloped, cache_age_secs(&state), result)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ListPageParams,
+        ListFilterParams,
+        ("with_reasons" = Option<bool>, Query, description = "List the criteria each validator fails"),
+    ),
+    responses(
+        (status = 200, description = "Validators failing at least one criterion", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 406, description = "Requested format cannot be produced", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 502, description = "Upstream sent a response that could not be used", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 503, description = "Upstream unreachable and nothing cached", body = ApiEnvelope<ValidatorsListResponse>),
+    )
+)]
+#[get("/api/v2/validators/ineligible")]
+async fn get_ineligible_validators_v2(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_ineligible_validators(&req, &state, &query).await;
+    render_list(ListShape::Enveloped, cache_age_secs(&state), result)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("limit" = Option<usize>, Query, description = "Validators to return, default 50"),
+        ("offset" = Option<usize>, Query, description = "Ranks to skip"),
+        ListFilterParams,
+    ),
+    responses(
+        (status = 200, description = "Highest ranked eligible validators", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 304, description = "If-None-Match names the current ETag"),
+        (status = 400, description = "Invalid query parameter", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 406, description = "Requested format cannot be produced", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 502, description = "Upstream sent a response that could not be used", body = ApiEnvelope<ValidatorsListResponse>),
+        (status = 503, description = "Upstream unreachable and nothing cached", body = ApiEnvelope<ValidatorsListResponse>),
+    )
+)]
+#[get("/api/v2/validators/top")]
+async fn get_top_validators_v2(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let result = list_top_validators(&req, &state, &query).await;
+    render_list(ListShape::Enveloped, cache_age_secs(&state), result)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("count" = Option<usize>, Query, description = "Validators to pick, default 1"),
+        ("seed" = Option<u64>, Query, description = "Seed for a reproducible pick, random when omitted"),
+        ("strategy" = Option<String>, Query, description = "weighted (default) or diversified"),
+        ("max_weight_share" = Option<f64>, Query, description = "Cap on one validator's share of the weight, diversified only"),
+        ("w_reward" = Option<f64>, Query, description = "Weight of the combined reward rate in a composite score; any w_* replaces reward x availability"),
+        ("w_availability" = Option<f64>, Query, description = "Weight of availability in the composite score"),
+        ("w_uptime" = Option<f64>, Query, description = "Weight of uptime_signed in the composite score"),
+        ("exclude" = Option<String>, Query, description = "Comma-separated delegation addresses to leave out"),
+        ("include_only" = Option<String>, Query, description = "Comma-separated delegation addresses to pick from"),
+        ("include_inactive" = Option<bool>, Query, description = "Also consider providers not reported active"),
+        ("exclude_outliers" = Option<bool>, Query, description = "Leave validators with an implausible combined rate out of the pool"),
+        ("min_availability" = Option<f64>, Query, description = "Drop validators below this availability before weighting"),
+        ("min_combined_reward" = Option<f64>, Query, description = "Drop validators below this combined rate before weighting"),
+        ("active" = Option<bool>, Query, description = "Keep only validators with this active flag"),
+        ("staking" = Option<bool>, Query, description = "Keep only validators with this staking condition"),
+        ("min_uptime_signed" = Option<u32>, Query, description = "Drop validators with a lower or unknown uptime_signed"),
+        ("total_amount" = Option<f64>, Query, description = "Stake to split across the picked validators"),
+        ("precision" = Option<u32>, Query, description = "Decimal places of the suggested amounts, default 2, at most 9"),
+        ("format" = Option<String>, Query, description = "json (default), or tx for the picks as WNat.batchDelegate arguments"),
+    ),
+    responses(
+        (status = 200, description = "Weighted random selection, or with format=tx the picks as a delegation transaction", body = SelectionBody),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/select")]
+async fn select_validators(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let count = match query.get("count").map(|c| c.parse::<usize>()) {
+        None => 1,
+        Some(Ok(count)) if count > 0 => count,
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'count' must be a positive integer"
+        })),
+    };
+    // Without a seed pick one at random and report it so the call can be replayed
+    let seed = match query.get("seed").map(|s| s.parse::<u64>()) {
+        None => rand::random(),
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'seed' must be an unsigned 64-bit integer"
+        })),
+    };
+
+    // Diversified selection caps each validator's share of the total weight
+    let max_weight_share = match query.get("strategy").map(String::as_str) {
+        None | Some("weighted") => None,
+        Some("diversified") => match query.get("max_weight_share").map(|m| m.parse::<f64>()) {
+            None => Some(DEFAULT_MAX_WEIGHT_SHARE),
+            Some(Ok(share)) if share > 0.0 && share <= 1.0 => Some(share),
+            Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "'max_weight_share' must be a number in (0, 1]"
+            })),
+        },
+        Some(other) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown strategy '{}', expected 'weighted' or 'diversified'", other)
+        })),
+    };
+
+    let pool = match PoolOptions::from_query(&query) {
+        Ok(pool) => pool,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+    let score_weights = pool.score_weights;
+
+    // Amounts are split in whole units of 10^-precision so they sum exactly
+    let precision = match query.get("precision").map(|p| p.parse::<u32>()) {
+        None => DEFAULT_AMOUNT_PRECISION,
+        Some(Ok(precision)) if precision <= MAX_AMOUNT_PRECISION => precision,
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("'precision' must be an integer from 0 to {}", MAX_AMOUNT_PRECISION)
+        })),
+    };
+    let unit = 10f64.powi(precision as i32);
+    let total_units = match query.get("total_amount").map(|a| a.parse::<f64>()) {
+        None => None,
+        // Beyond 2^53 units f64 can no longer represent every amount exactly
+        Some(Ok(amount)) if amount > 0.0 && (amount * unit).round() < 2f64.powi(53) => {
+            Some((amount * unit).round() as u64)
+        },
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'total_amount' must be a positive number small enough to split at the requested precision"
+        })),
+    };
+    let as_tx = match query.get("format").map(String::as_str) {
+        None | Some("json") => false,
+        Some("tx") => true,
+        Some(other) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown format '{}', expected 'json' or 'tx'", other)
+        })),
+    };
+
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let outliers = if pool.exclude_outliers { cached_outliers(&state, &data) } else { Vec::new() };
+            let (candidates, inactive) = match pool.candidates(&data.eligible_nodes, &outliers) {
+                Ok(pool) => pool,
+                Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+            };
+
+            let weights = pool.weights(&candidates);
+            let total_weight: f64 = weights.iter().sum();
+            // Capped weights keep the same total so they stay comparable to `weight`
+            let effective: Option<Vec<f64>> = max_weight_share.map(|share| {
+                cap_weight_shares(&weights, share).into_iter().map(|s| s * total_weight).collect()
+            });
+            let sampling_weights = effective.as_ref().unwrap_or(&weights);
+
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let picked = if effective.is_some() {
+                // Sample everyone so later picks can come from unused providers
+                let ranked = weighted_sample(sampling_weights, sampling_weights.len(), &mut rng);
+                spread_by_provider(ranked, &candidates).into_iter().take(count).collect()
+            } else {
+                weighted_sample(sampling_weights, count, &mut rng)
+            };
+
+            let picked_weights: Vec<f64> = picked.iter().map(|&i| sampling_weights[i]).collect();
+            let amounts = total_units.map(|total| allocate_units(total, &picked_weights));
+
+            if as_tx {
+                let picks: Vec<&Validator> = picked.iter().map(|&i| candidates[i]).collect();
+                return match delegation_tx(seed, &picks, &picked_weights, amounts.as_deref(), precision) {
+                    Ok(tx) => HttpResponse::Ok()
+                        .insert_header(cache_status.header())
+                        .json(SelectionBody::Tx(timestamped(&data.timestamp, tx))),
+                    Err(e) => HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+                };
+            }
+
+            let validators: Vec<SelectedValidator> = picked.iter()
+                .enumerate()
+                .map(|(n, &i)| SelectedValidator {
+                    validator: candidates[i].clone(),
+                    weight: weights[i],
+                    effective_weight: effective.as_ref().map(|e| e[i]),
+                    probability: if total_weight > 0.0 { sampling_weights[i] / total_weight } else { 0.0 },
+                    amount: amounts.as_ref().map(|a| a[n] as f64 / unit),
+                    composite_score: score_weights.map(|_| weights[i]),
+                })
+                .collect();
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(SelectionBody::Selection(timestamped(&data.timestamp, SelectionResponse {
+                    timestamp: data.timestamp.clone(),
+                    strategy: if max_weight_share.is_some() { "diversified" } else { "weighted" }.to_string(),
+                    max_weight_share,
+                    score_weights,
+                    seed,
+                    count: validators.len(),
+                    total_weight,
+                    total_amount: total_units.map(|total| total as f64 / unit),
+                    excluded_inactive: inactive.len(),
+                    candidate_pool: candidates.len(),
+                    excluded_outliers: outliers,
+                    validators,
+                })))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("seed" = Option<u64>, Query, description = "Seed for a reproducible pick, random when omitted"),
+        ("weighted" = Option<bool>, Query, description = "Weight by combined rate and availability instead of picking uniformly"),
+    ),
+    responses(
+        (status = 200, description = "One eligible validator", body = Timestamped<RandomPickResponse>),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 404, description = "No validator is eligible"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/random")]
+async fn random_validator(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let seed = match query.get("seed").map(|s| s.parse::<u64>()) {
+        None => rand::random(),
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'seed' must be an unsigned 64-bit integer"
+        })),
+    };
+    let weighted = match query.get("weighted").map(|w| w.parse::<bool>()) {
+        None => false,
+        Some(Ok(weighted)) => weighted,
+        Some(Err(_)) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'weighted' must be 'true' or 'false'"
+        })),
+    };
+
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let weights: Vec<f64> = data.eligible_nodes.iter().map(selection_weight).collect();
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let Some((i, probability)) = pick_one(&weights, weighted, &mut rng) else {
+                return AppError::NotFound("No eligible validators to pick from".to_string()).error_response();
+            };
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, RandomPickResponse {
+                    timestamp: data.timestamp.clone(),
+                    seed,
+                    weighted,
+                    pool_size: weights.len(),
+                    validator: SelectedValidator {
+                        validator: data.eligible_nodes[i].clone(),
+                        weight: weights[i],
+                        effective_weight: None,
+                        probability,
+                        amount: None,
+                        composite_score: None,
+                    },
+                }))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+// Rebalancing advice for an existing split: the held total is allocated like
+// ?total_amount= on /select, but over the `count` highest-weighted candidates
+// instead of a random draw, so the recommendation is the same on every call
+// for the same cached data
+#[utoipa::path(
+    tag = "validators",
+    request_body = Vec<DriftHolding>,
+    params(
+        ("count" = Option<usize>, Query, description = "Validators to recommend, default the number of positions held"),
+        ("precision" = Option<u32>, Query, description = "Decimal places the amounts are compared at, default 2, at most 9"),
+        ("w_reward" = Option<f64>, Query, description = "Weight of the combined reward rate in a composite score; any w_* replaces reward x availability"),
+        ("w_availability" = Option<f64>, Query, description = "Weight of availability in the composite score"),
+        ("w_uptime" = Option<f64>, Query, description = "Weight of uptime_signed in the composite score"),
+        ("exclude" = Option<String>, Query, description = "Comma-separated delegation addresses to leave out"),
+        ("include_only" = Option<String>, Query, description = "Comma-separated delegation addresses to pick from"),
+        ("include_inactive" = Option<bool>, Query, description = "Also consider providers not reported active"),
+        ("exclude_outliers" = Option<bool>, Query, description = "Leave validators with an implausible combined rate out of the pool"),
+        ("min_availability" = Option<f64>, Query, description = "Drop validators below this availability before weighting"),
+        ("min_combined_reward" = Option<f64>, Query, description = "Drop validators below this combined rate before weighting"),
+        ("active" = Option<bool>, Query, description = "Keep only validators with this active flag"),
+        ("staking" = Option<bool>, Query, description = "Keep only validators with this staking condition"),
+        ("min_uptime_signed" = Option<u32>, Query, description = "Drop validators with a lower or unknown uptime_signed"),
+    ),
+    responses(
+        (status = 200, description = "How the posted split differs from the recommended one", body = Timestamped<DriftResponse>),
+        (status = 400, description = "Invalid holdings or query parameter"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[post("/api/select/drift")]
+async fn selection_drift_advice(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>,
+    body: web::Json<Vec<DriftHolding>>,
+) -> impl Responder {
+    let holdings = body.into_inner();
+    if holdings.is_empty() || holdings.len() > MAX_DRIFT_HOLDINGS {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Post between 1 and {} holdings", MAX_DRIFT_HOLDINGS)
+        }));
+    }
+    let mut seen = HashSet::new();
+    if let Some(h) = holdings.iter().find(|h| !seen.insert(h.id)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Validator {} is listed more than once", h.id)
+        }));
+    }
+
+    let count = match query.get("count").map(|c| c.parse::<usize>()) {
+        None => holdings.len(),
+        Some(Ok(count)) if count > 0 => count,
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'count' must be a positive integer"
+        })),
+    };
+    let precision = match query.get("precision").map(|p| p.parse::<u32>()) {
+        None => DEFAULT_AMOUNT_PRECISION,
+        Some(Ok(precision)) if precision <= MAX_AMOUNT_PRECISION => precision,
+        Some(_) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("'precision' must be an integer from 0 to {}", MAX_AMOUNT_PRECISION)
+        })),
+    };
+    let unit = 10f64.powi(precision as i32);
+    // Same bound as ?total_amount= on /select, for the sum as well as each amount
+    let total: f64 = holdings.iter().map(|h| h.amount).sum();
+    if holdings.iter().any(|h| !h.amount.is_finite() || h.amount < 0.0) || total <= 0.0 || (total * unit).round() >= 2f64.powi(53) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Amounts must be non-negative numbers with a positive total small enough to split at the requested precision"
+        }));
+    }
+    let held: Vec<(u32, u64)> = holdings.iter().map(|h| (h.id, (h.amount * unit).round() as u64)).collect();
+    let pool = match PoolOptions::from_query(&query) {
+        Ok(pool) => pool,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let outliers = if pool.exclude_outliers { cached_outliers(&state, &data) } else { Vec::new() };
+            let (candidates, _) = match pool.candidates(&data.eligible_nodes, &outliers) {
+                Ok(pool) => pool,
+                Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+            };
+
+            let weights = pool.weights(&candidates);
+            let mut ranked: Vec<usize> = (0..candidates.len()).collect();
+            ranked.sort_by(|&a, &b| weights[b].total_cmp(&weights[a]).then(candidates[a].id.cmp(&candidates[b].id)));
+            ranked.truncate(count);
+
+            let top_weights: Vec<f64> = ranked.iter().map(|&i| weights[i]).collect();
+            let total_units = held.iter().map(|&(_, units)| units).sum();
+            let recommended: Vec<(u32, u64)> = ranked.iter()
+                .map(|&i| candidates[i].id)
+                .zip(allocate_units(total_units, &top_weights))
+                .collect();
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, selection_drift(&data, &held, &recommended, unit)))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+// Narrows the eligible set to the validators selection may weight, in order:
+// the excluded outliers and list filters, then the address lists, then the
+// inactive rule. Returns the
+// candidates and the inactive validators set aside, or why nothing is left. An
+// empty eligible set is not an error here.
+fn selection_pool<'a>(
+    eligible: &'a [Validator],
+    outliers: &[u32],
+    filter: &ValidatorFilter,
+    addresses: &AddressFilter,
+    include_inactive: bool,
+) -> Result<(Vec<&'a Validator>, Vec<&'a Validator>), String> {
+    let matching: Vec<&Validator> = eligible.iter()
+        .filter(|v| !outliers.contains(&v.id) && filter.matches(v))
+        .collect();
+    if matching.is_empty() && !eligible.is_empty() {
+        return Err(format!("No candidates left: the filters removed all {} eligible validators", eligible.len()));
+    }
+    let allowed: Vec<&Validator> = matching.iter()
+        .copied()
+        .filter(|v| addresses.allows(v))
+        .collect();
+    if allowed.is_empty() && !matching.is_empty() {
+        return Err(format!(
+            "No candidates left: 'exclude'/'include_only' removed all {} eligible validators",
+            matching.len()
+        ));
+    }
+    let (candidates, inactive): (Vec<&Validator>, Vec<&Validator>) = allowed.into_iter()
+        .partition(|v| include_inactive || provider_active(v));
+    if candidates.is_empty() && !inactive.is_empty() {
+        return Err(format!(
+            "No candidates left: all {} remaining validators have inactive providers, \
+             pass include_inactive=true to consider them",
+            inactive.len()
+        ));
+    }
+    Ok((candidates, inactive))
+}
+
+// The query parameters that decide which eligible validators selection may
+// weight and how, shared by /api/validators/select and /api/select/drift
+struct PoolOptions {
+    filter: ValidatorFilter,
+    addresses: AddressFilter,
+    include_inactive: bool,
+    exclude_outliers: bool,
+    score_weights: Option<ScoreWeights>,
+}
+
+impl PoolOptions {
+    fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        let flag = |key: &str| match query.get(key).map(|v| v.parse::<bool>()) {
+            None => Ok(false),
+            Some(Ok(value)) => Ok(value),
+            Some(Err(_)) => Err(format!("'{}' must be 'true' or 'false'", key)),
+        };
+        Ok(PoolOptions {
+            score_weights: ScoreWeights::from_query(query)?,
+            filter: ValidatorFilter::from_query(query)?,
+            addresses: AddressFilter::from_query(query)?,
+            include_inactive: flag("include_inactive")?,
+            exclude_outliers: flag("exclude_outliers")?,
+        })
+    }
+
+    fn candidates<'a>(&self, eligible: &'a [Validator], outliers: &[u32]) -> Result<(Vec<&'a Validator>, Vec<&'a Validator>), String> {
+        selection_pool(eligible, outliers, &self.filter, &self.addresses, self.include_inactive)
+    }
+
+    // The composite score when ?w_*= is given, selection_weight() otherwise
+    fn weights(&self, candidates: &[&Validator]) -> Vec<f64> {
+        match &self.score_weights {
+            Some(score_weights) => score_weights.scores(candidates),
+            None => candidates.iter().copied().map(selection_weight).collect(),
+        }
+    }
+}
+
+// Selection only suggests stake for providers reported active; a missing flag
+// counts as inactive since it can't be told apart from a dormant provider
+fn provider_active(validator: &Validator) -> bool {
+    validator.provider_stats.as_ref().and_then(|p| p.active) == Some(true)
+}
+
+// Narrows the selection pool by delegation address, compared case-insensitively
+#[derive(Debug, Default)]
+struct AddressFilter {
+    exclude: HashSet<String>,
+    // None means every address is allowed
+    include_only: Option<HashSet<String>>,
+}
+
+impl AddressFilter {
+    fn from_query(query: &HashMap<String, String>) -> Result<Self, String> {
+        let parse = |key: &str| query.get(key).map(|raw| {
+            raw.split(',')
+                .map(|a| a.trim().to_lowercase())
+                .filter(|a| !a.is_empty())
+                .collect::<HashSet<String>>()
+        });
+        let exclude = parse("exclude").unwrap_or_default();
+        let include_only = parse("include_only");
+
+        if let Some(include_only) = &include_only {
+            if include_only.is_empty() {
+                return Err("'include_only' must list at least one delegation address".to_string());
+            }
+            let mut conflicts: Vec<&String> = include_only.intersection(&exclude).collect();
+            if !conflicts.is_empty() {
+                conflicts.sort();
+                return Err(format!(
+                    "Addresses are both excluded and included: {}",
+                    conflicts.iter().map(|a| a.as_str()).collect::<Vec<_>>().join(", ")
+                ));
+            }
+        }
+        Ok(AddressFilter { exclude, include_only })
+    }
+
+    // Validators without a delegation address can't match include_only
+    fn allows(&self, validator: &Validator) -> bool {
+        let address = validator.delegation_address.as_deref().map(str::to_lowercase);
+        let excluded = address.as_ref().is_some_and(|a| self.exclude.contains(a));
+        let included = self.include_only.as_ref()
+            .is_none_or(|only| address.as_ref().is_some_and(|a| only.contains(a)));
+        included && !excluded
+    }
+}
+
+// Ids in request order with repeats dropped
+fn parse_compare_ids(query: &HashMap<String, String>) -> Result<Vec<u32>, String> {
+    let raw = query.get("ids").ok_or("'ids' is required, e.g. ?ids=1,2,3")?;
+    let mut seen = HashSet::new();
+    let mut ids = Vec::new();
+    for part in raw.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let id: u32 = part.parse().map_err(|_| format!("Invalid validator id '{}'", part))?;
+        if seen.insert(id) {
+            ids.push(id);
+        }
+    }
+
+    if ids.is_empty() {
+        return Err("'ids' must list at least one validator id".to_string());
+    }
+    if ids.len() > MAX_COMPARE_IDS {
+        return Err(format!("At most {} validators can be compared at once, got {}", MAX_COMPARE_IDS, ids.len()));
+    }
+    Ok(ids)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(("ids" = String, Query, description = "Comma-separated validator ids, at most 25")),
+    responses(
+        (status = 200, description = "The requested validators with their eligible ranks", body = Timestamped<CompareResponse>),
+        (status = 400, description = "Missing or invalid ids"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/compare")]
+async fn compare_validators(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let ids = match parse_compare_ids(&query) {
+        Ok(ids) => ids,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({ "error": e })),
+    };
+
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let mut validators = Vec::new();
+            let mut not_found = Vec::new();
+            for id in ids {
+                if let Some(i) = data.eligible_nodes.iter().position(|v| v.id == id) {
+                    validators.push(ComparedValidator {
+                        validator: data.eligible_nodes[i].clone(),
+                        eligible: true,
+                        rank: Some(i + 1),
+                    });
+                } else if let Some(v) = data.ineligible_nodes.iter().find(|v| v.id == id) {
+                    validators.push(ComparedValidator { validator: v.clone(), eligible: false, rank: None });
+                } else {
+                    not_found.push(CompareMissing { id, error: "Validator not found".to_string() });
+                }
+            }
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, CompareResponse {
+                    timestamp: data.timestamp.clone(),
+                    eligible_count: data.eligible_count,
+                    count: validators.len(),
+                    validators,
+                    not_found,
+                }))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+// Levenshtein distance between `query` and the closest-matching substring of
+// `name`, ignoring case: 0 when the query appears in the name as is
+fn fuzzy_distance(query: &str, name: &str) -> usize {
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    // Column per name char; row 0 stays 0 so a match can start anywhere
+    let mut prev: Vec<usize> = (0..=query.len()).collect();
+    let mut best = query.len();
+    for &c in &name {
+        let mut curr = vec![0; query.len() + 1];
+        for i in 1..=query.len() {
+            let substitute = prev[i - 1] + usize::from(query[i - 1] != c);
+            curr[i] = substitute.min(prev[i] + 1).min(curr[i - 1] + 1);
+        }
+        best = best.min(curr[query.len()]);
+        prev = curr;
+    }
+    best
+}
+
+// Allow one typo per three query chars, so short queries must match exactly
+fn max_fuzzy_distance(query: &str) -> usize {
+    query.chars().count() / 3
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("q" = String, Query, description = "Part of a validator name, at least 2 characters"),
+        ("fuzzy" = Option<bool>, Query, description = "Tolerate one typo per three query characters"),
+    ),
+    responses(
+        (status = 200, description = "Validators whose name matches", body = Timestamped<SearchResponse>),
+        (status = 400, description = "Query too short or invalid fuzzy flag"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/search")]
+async fn search_validators(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let q = query.get("q").map(|q| q.trim()).unwrap_or_default();
+    if q.chars().count() < MIN_SEARCH_QUERY_LEN {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("'q' must be at least {} characters", MIN_SEARCH_QUERY_LEN)
+        }));
+    }
+    let fuzzy = match query.get("fuzzy").map(|f| f.parse::<bool>()) {
+        None => false,
+        Some(Ok(fuzzy)) => fuzzy,
+        Some(Err(_)) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'fuzzy' must be 'true' or 'false'"
+        })),
+    };
+
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let needle = q.to_lowercase();
+            let max_distance = max_fuzzy_distance(q);
+            let ranked = data.eligible_nodes.iter().enumerate().map(|(i, v)| (v, Some(i + 1)));
+            let unranked = data.ineligible_nodes.iter().map(|v| (v, None));
+
+            // Eligible validators in rank order come first, then ineligible ones
+            let mut matches: Vec<SearchMatch> = ranked.chain(unranked)
+                .filter_map(|(v, rank)| {
+                    let distance = if fuzzy {
+                        Some(fuzzy_distance(q, &v.name)).filter(|&d| d <= max_distance)?
+                    } else if v.name.to_lowercase().contains(&needle) {
+                        0
+                    } else {
+                        return None;
+                    };
+                    Some(SearchMatch {
+                        validator: v.clone(),
+                        eligible: rank.is_some(),
+                        rank,
+                        distance: fuzzy.then_some(distance),
+                    })
+                })
+                .collect();
+            if fuzzy {
+                // Stable, so equally close matches keep the list order
+                matches.sort_by_key(|m| m.distance);
+            }
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, SearchResponse {
+                    timestamp: data.timestamp.clone(),
+                    query: q.to_string(),
+                    fuzzy,
+                    count: matches.len(),
+                    matches,
+                }))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+#[utoipa::path(
+    tag = "history",
+    params(
+        ("from" = String, Query, description = "RFC 3339 timestamp"),
+        ("to" = String, Query, description = "RFC 3339 timestamp"),
+    ),
+    responses(
+        (status = 200, description = "Eligibility and rank changes between two snapshots", body = Timestamped<DiffRangeResponse>),
+        (status = 400, description = "Missing or invalid timestamps"),
+        (status = 404, description = "No snapshot covers the range"),
+    )
+)]
+#[get("/api/validators/diff-range")]
+async fn get_diff_range(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let parse_ts = |key: &str| {
+        query.get(key)
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc))
+    };
+
+    let (Some(from), Some(to)) = (parse_ts("from"), parse_ts("to")) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Both 'from' and 'to' must be RFC 3339 timestamps"
+        }));
+    };
+    if from > to {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'from' must not be later than 'to'"
+        }));
+    }
+
+    let history = state.history.read();
+    let (Some(oldest), Some(newest)) = (history.front(), history.back()) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No snapshots have been recorded yet"
+        }));
+    };
+
+    match (snapshot_at(&history, from), snapshot_at(&history, to)) {
+        // The later snapshot is the newest data the diff is built from
+        (Some(from_snapshot), Some(to_snapshot)) => HttpResponse::Ok().json(timestamped(
+            &to_snapshot.timestamp.to_rfc3339(),
+            diff_snapshots(from_snapshot, to_snapshot),
+        )),
+        _ => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No snapshot available for the requested range",
+            "available_from": oldest.timestamp.to_rfc3339(),
+            "available_to": newest.timestamp.to_rfc3339(),
+        })),
+    }
+}
+
+// For clients polling often: what changed since their last poll, from the
+// per-refresh snapshots rather than a full list
+#[utoipa::path(
+    tag = "history",
+    params(("since" = String, Query, description = "RFC 3339 timestamp of the client's previous poll")),
+    responses(
+        (status = 200, description = "Validators changed, added and removed since the timestamp", body = Timestamped<ChangesResponse>),
+        (status = 400, description = "Missing or invalid timestamp"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/changes")]
+async fn get_changes(
+    state: web::Data<Arc<AppState>>,
+    query: web::Query<HashMap<String, String>>
+) -> impl Responder {
+    let Some(since) = query.get("since")
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc))
+    else {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'since' must be an RFC 3339 timestamp"
+        }));
+    };
+
+    let (data, cache_status) = match fetch_validator_data(&state).await {
+        Ok(fetched) => fetched,
+        Err(e) => return e.error_response(),
+    };
+
+    let history = state.history.read();
+    let available_from = history.front().map(|s| s.timestamp.to_rfc3339());
+    let mut response = ChangesResponse {
+        since: since.to_rfc3339(),
+        baseline: None,
+        timestamp: data.timestamp.clone(),
+        full_refetch_required: true,
+        available_from,
+        changed: Vec::new(),
+        added: Vec::new(),
+        removed: Vec::new(),
+    };
+    // Without a snapshot from before since, changes could have been missed
+    if let Some(baseline) = snapshot_at(&history, since) {
+        let (changed, added, removed) = changes_since(baseline, &data);
+        response.baseline = Some(baseline.timestamp.to_rfc3339());
+        response.full_refetch_required = false;
+        response.changed = changed;
+        response.added = added;
+        response.removed = removed;
+    }
+
+    HttpResponse::Ok()
+        .insert_header(cache_status.header())
+        .json(timestamped(&data.timestamp, response))
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("node_id" = String, Path, description = "Node id, e.g. NodeID-..."),
+        ("format" = Option<String>, Query, description = "json (default), yaml, csv or msgpack; overrides Accept"),
+    ),
+    responses(
+        (status = 200, description = "The matching validator", body = Timestamped<Validator>),
+        (status = 404, description = "No validator runs this node"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/by-node/{node_id}")]
+async fn get_validator_by_node(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let node_id = path.into_inner();
+    let format = match ResponseFormat::negotiate(&req, &query) {
+        Ok(format) => format,
+        Err(e) => return vary_on_accept(not_acceptable(e)),
+    };
+
+    let response = match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let validator = data.eligible_nodes.iter()
+                .chain(data.ineligible_nodes.iter())
+                .find(|v| v.node_ids.contains(&node_id));
+
+            match validator {
+                Some(v) => validator_detail(format, cache_status, &data.timestamp, v),
+                None => AppError::NotFound(format!("No validator runs node '{}'", node_id)).error_response(),
+            }
+        },
+        Err(e) => e.error_response(),
+    };
+    vary_on_accept(response)
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("address" = String, Path, description = "Delegation address, compared case-insensitively"),
+        ("format" = Option<String>, Query, description = "json (default), yaml, csv or msgpack; overrides Accept"),
+    ),
+    responses(
+        (status = 200, description = "The matching validator", body = Timestamped<Validator>),
+        (status = 404, description = "No validator has this delegation address"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/by-delegation/{address}")]
+async fn get_validator_by_delegation(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let address = path.into_inner();
+    let format = match ResponseFormat::negotiate(&req, &query) {
+        Ok(format) => format,
+        Err(e) => return vary_on_accept(not_acceptable(e)),
+    };
+
+    let response = match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => {
+            let validator = data.eligible_nodes.iter()
+                .chain(data.ineligible_nodes.iter())
+                .find(|v| v.delegation_address.as_deref().is_some_and(|a| a.eq_ignore_ascii_case(&address)));
+
+            match validator {
+                Some(v) => validator_detail(format, cache_status, &data.timestamp, v),
+                None => AppError::NotFound(format!("No validator has delegation address '{}'", address)).error_response(),
+            }
+        },
+        Err(e) => e.error_response(),
+    };
+    vary_on_accept(response)
+}
+
+// One validator in the negotiated format; as CSV it is a single row
+fn validator_detail(format: ResponseFormat, cache_status: CacheStatus, data_timestamp: &str, v: &Validator) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    builder.insert_header(cache_status.header());
+    formatted(builder, format, &timestamped(data_timestamp, v), || Some(render_validators_csv(&[v])))
+}
+
+// None when upstream has no such entity or no single-entity endpoint; both
+// answer 404, so the caller falls back to the full list either way
+async fn fetch_single_entity(state: &AppState, id: u32) -> Result<Option<FlareEntity>, FetchError> {
+    let url = format!("{}/entity/{}", state.flare_api, id);
+    let response = state.http_client.get(&url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let mut raw: serde_json::Value = read_json(response, state.body_timeout).await?;
+    apply_condition_aliases(&mut raw, state.network);
+    serde_json::from_value(raw).map(Some).map_err(FetchError::Decode)
+}
+
+// A validator the full list doesn't have yet, from the entity cache or a
+// single upstream request. Without the rest of the list there is no rank,
+// rate_vs_median or normalized_combined, only the eligibility check itself.
+// Unknown ids are remembered too, so asking for one again doesn't reach
+// upstream before the next refresh.
+async fn lookup_single_validator(state: &AppState, id: u32) -> Option<(Validator, SystemTime, CacheStatus)> {
+    if let Some((v, fetched_at)) = state.entity_cache.lock().get(&id) {
+        if SystemTime::now().duration_since(*fetched_at).is_ok_and(|age| age < state.cache_ttl) {
+            return v.clone().map(|v| (v, *fetched_at, CacheStatus::Hit));
+        }
+    }
+
+    let v = match fetch_single_entity(state, id).await {
+        Ok(Some(entity)) if entity.id == id => {
+            let mut v = process_entity(&entity, state.epochs_per_year, state.availability_scale);
+            let no_ignore = HashSet::new();
+            if !is_eligible_ignoring(&v, &no_ignore, &state.criteria) {
+                v.unmet_criteria = Some(unmet_criteria(&v, &no_ignore, &state.criteria));
+            }
+            Some(v)
+        },
+        Ok(_) => None,
+        // Not remembered, the next request may find upstream healthy again
+        Err(e) => {
+            log::debug!("Single-entity lookup of {} failed, falling back to the full list: {}", id, e);
+            return None;
+        },
+    };
+
+    let fetched_at = SystemTime::now();
+    let mut entity_cache = state.entity_cache.lock();
+    if entity_cache.len() >= MAX_ENTITY_CACHE {
+        entity_cache.retain(|_, (_, at)| SystemTime::now().duration_since(*at).is_ok_and(|age| age < state.cache_ttl));
+        if entity_cache.len() >= MAX_ENTITY_CACHE {
+            entity_cache.clear();
+        }
+    }
+    entity_cache.insert(id, (v.clone(), fetched_at));
+    v.map(|v| (v, fetched_at, CacheStatus::Miss))
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("id" = u32, Path, description = "Validator entity id"),
+        ("format" = Option<String>, Query, description = "json (default), yaml, csv or msgpack; overrides Accept"),
+    ),
+    responses(
+        (status = 200, description = "The matching validator", body = Timestamped<Validator>),
+        (status = 404, description = "Validator not found"),
+        (status = 406, description = "Requested format cannot be produced"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/{id}")]
+async fn get_validator_by_id(
+    req: actix_web::HttpRequest,
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<u32>,
+    query: web::Query<HashMap<String, String>>,
+) -> impl Responder {
+    let validator_id = path.into_inner();
+    let format = match ResponseFormat::negotiate(&req, &query) {
+        Ok(format) => format,
+        Err(e) => return vary_on_accept(not_acceptable(e)),
+    };
+    let find = |data: &ValidatorResponse| data.eligible_nodes.iter()
+        .chain(data.ineligible_nodes.iter())
+        .find(|v| v.id == validator_id)
+        .cloned();
+
+    // Only an empty cache makes fetch_validator_data go upstream, and a
+    // single-entity request is far cheaper than the full list
+    let listed = if state.cache.read().is_some() { Some(fetch_validator_data(&state).await) } else { None };
+    if let Some(Ok((data, cache_status))) = &listed {
+        if let Some(v) = find(data) {
+            return vary_on_accept(validator_detail(format, *cache_status, &data.timestamp, &v));
+        }
+    }
+
+    // Merged ids only make sense against the full list
+    if !state.dedup_entities {
+        if let Some((v, fetched_at, cache_status)) = lookup_single_validator(&state, validator_id).await {
+            let data_timestamp = DateTime::<Utc>::from(fetched_at).to_rfc3339();
+            return vary_on_accept(validator_detail(format, cache_status, &data_timestamp, &v));
+        }
     }
-}
 
-#[get("/")]
-async fn usage() -> impl Responder {
-    HttpResponse::Ok().json(UsageResponse {
-        api_name: "Flare Validator API".to_string(),
-        version: "1.0.0".to_string(),
-        endpoints: vec![
-            "/health".to_string(),
-            "/api/validators".to_string(),
-            "/api/validators/eligible".to_string(),
-            "/api/validators/ineligible".to_string(),
-            "/api/validators/top?limit=N".to_string(),
-            "/api/validators/{id}".to_string(),
-            "/api/refresh".to_string(),
-        ],
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    })
+    let listed = match listed {
+        Some(listed) => listed,
+        None => fetch_validator_data(&state).await,
+    };
+    let response = match listed {
+        Ok((data, cache_status)) => match find(&data) {
+            Some(v) => validator_detail(format, cache_status, &data.timestamp, &v),
+            None => AppError::NotFound("Validator not found".to_string()).error_response(),
+        },
+        Err(e) => e.error_response(),
+    };
+    vary_on_accept(response)
 }
 
-#[get("/health")]
-async fn health_check() -> impl Responder {
-    HttpResponse::Ok().json(HealthResponse {
-        status: "ok".to_string(),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    })
+#[utoipa::path(
+    tag = "history",
+    params(("id" = u32, Path, description = "Validator entity id")),
+    responses(
+        (status = 200, description = "Reward rate samples from recent refreshes", body = Timestamped<RateHistoryResponse>),
+        (status = 404, description = "No history recorded for this validator"),
+    )
+)]
+#[get("/api/validators/{id}/history")]
+async fn get_validator_history(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let validator_id = path.into_inner();
+    let history = state.history.read();
+    let samples = rate_history(&history, validator_id);
+
+    let Some(latest) = history.back().filter(|_| !samples.is_empty()) else {
+        return AppError::NotFound(format!("No history recorded for validator {}", validator_id)).error_response();
+    };
+    HttpResponse::Ok().json(timestamped(&latest.timestamp.to_rfc3339(), RateHistoryResponse {
+        id: validator_id,
+        count: samples.len(),
+        samples,
+    }))
 }
 
-#[get("/api/validators")]
-async fn get_all_validators(state: web::Data<Arc<AppState>>) -> impl Responder {
+#[utoipa::path(
+    tag = "validators",
+    params(("id" = u32, Path, description = "Validator entity id")),
+    responses(
+        (status = 200, description = "Delegation address and wallet link", body = Timestamped<DelegationUrlResponse>),
+        (status = 404, description = "Validator not found"),
+        (status = 422, description = "Validator has no delegation address"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/validators/{id}/delegation-url")]
+async fn get_delegation_url(
+    state: web::Data<Arc<AppState>>,
+    path: web::Path<u32>,
+) -> impl Responder {
+    let validator_id = path.into_inner();
+
     match fetch_validator_data(&state).await {
-        Ok(data) => HttpResponse::Ok().json(data),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to fetch validator data"
-        })),
+        Ok((data, cache_status)) => {
+            let validator = data.eligible_nodes.iter()
+                .chain(data.ineligible_nodes.iter())
+                .find(|v| v.id == validator_id);
+            let Some(v) = validator else {
+                return AppError::NotFound("Validator not found".to_string()).error_response();
+            };
+            let Some(address) = &v.delegation_address else {
+                return HttpResponse::UnprocessableEntity().json(serde_json::json!({
+                    "error": "Validator has no delegation address and cannot receive delegations"
+                }));
+            };
+
+            let url = state.delegation_url_template.as_ref().map(|template| {
+                template
+                    .replace("{address}", address)
+                    .replace("{id}", &v.id.to_string())
+                    .replace("{node_id}", v.node_id.as_deref().unwrap_or(""))
+            });
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, DelegationUrlResponse {
+                    id: v.id,
+                    name: v.name.clone(),
+                    delegation_address: address.clone(),
+                    url,
+                }))
+        },
+        Err(e) => e.error_response(),
     }
 }
 
-#[get("/api/validators/eligible")]
-async fn get_eligible_validators(state: web::Data<Arc<AppState>>) -> impl Responder {
+#[utoipa::path(
+    tag = "validators",
+    request_body = NodeStatusRequest,
+    responses(
+        (status = 200, description = "Eligibility of each node id", body = Timestamped<NodeStatusResponse>),
+        (status = 400, description = "More than 500 node ids"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[post("/api/nodes/status")]
+async fn get_nodes_status(
+    state: web::Data<Arc<AppState>>,
+    body: web::Json<NodeStatusRequest>,
+) -> impl Responder {
+    let node_ids = body.into_inner().node_ids;
+    if node_ids.len() > MAX_NODE_STATUS_BATCH {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} node ids can be checked per request", MAX_NODE_STATUS_BATCH)
+        }));
+    }
+
     match fetch_validator_data(&state).await {
-        Ok(data) => HttpResponse::Ok().json(ValidatorsListResponse {
-            timestamp: data.timestamp,
-            count: data.eligible_count,
-            validators: data.eligible_nodes,
-        }),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to fetch eligible validators"
-        })),
+        Ok((data, cache_status)) => {
+            let validators: HashMap<&str, (&Validator, bool)> = data.eligible_nodes.iter()
+                .map(|v| (v, true))
+                .chain(data.ineligible_nodes.iter().map(|v| (v, false)))
+                .flat_map(|(v, eligible)| v.node_ids.iter().map(move |id| (id.as_str(), (v, eligible))))
+                .collect();
+
+            let mut unknown_node_ids = Vec::new();
+            let nodes = node_ids.into_iter()
+                .map(|node_id| match validators.get(node_id.as_str()) {
+                    Some((v, eligible)) => NodeStatus {
+                        node_id,
+                        found: true,
+                        validator_id: Some(v.id),
+                        name: Some(v.name.clone()),
+                        eligible: Some(*eligible),
+                        conditions: v.conditions.clone(),
+                        reward_rates: v.reward_rates.clone(),
+                    },
+                    None => {
+                        unknown_node_ids.push(node_id.clone());
+                        NodeStatus {
+                            node_id,
+                            found: false,
+                            validator_id: None,
+                            name: None,
+                            eligible: None,
+                            conditions: None,
+                            reward_rates: None,
+                        }
+                    },
+                })
+                .collect();
+
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, NodeStatusResponse {
+                    timestamp: data.timestamp.clone(),
+                    nodes,
+                    unknown_node_ids,
+                }))
+        },
+        Err(e) => e.error_response(),
     }
 }
 
-#[get("/api/validators/ineligible")]
-async fn get_ineligible_validators(state: web::Data<Arc<AppState>>) -> impl Responder {
-    match fetch_validator_data(&state).await {
-        Ok(data) => HttpResponse::Ok().json(ValidatorsListResponse {
-            timestamp: data.timestamp,
-            count: data.ineligible_count,
-            validators: data.ineligible_nodes,
-        }),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to fetch ineligible validators"
-        })),
+// Indexes both lists once, then resolves every requested key against the index
+fn batch_lookup(data: &ValidatorResponse, ids: &[u32], node_ids: &[String]) -> BatchLookupResponse {
+    let ranked = data.eligible_nodes.iter()
+        .enumerate()
+        .map(|(i, v)| (v, Some(i + 1)))
+        .chain(data.ineligible_nodes.iter().map(|v| (v, None)));
+    let mut by_id: HashMap<u32, (&Validator, Option<usize>)> = HashMap::new();
+    let mut by_node: HashMap<&str, (&Validator, Option<usize>)> = HashMap::new();
+    for (v, rank) in ranked {
+        by_id.insert(v.id, (v, rank));
+        for node_id in &v.node_ids {
+            by_node.insert(node_id.as_str(), (v, rank));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut validators = Vec::new();
+    let mut add = |(v, rank): (&Validator, Option<usize>)| {
+        if seen.insert(v.id) {
+            validators.push(ComparedValidator { validator: v.clone(), eligible: rank.is_some(), rank });
+        }
+    };
+    let mut not_found_ids = Vec::new();
+    for &id in ids {
+        match by_id.get(&id) {
+            Some(&found) => add(found),
+            None if !not_found_ids.contains(&id) => not_found_ids.push(id),
+            None => {},
+        }
+    }
+    let mut not_found_node_ids = Vec::new();
+    for node_id in node_ids {
+        match by_node.get(node_id.as_str()) {
+            Some(&found) => add(found),
+            None if !not_found_node_ids.contains(node_id) => not_found_node_ids.push(node_id.clone()),
+            None => {},
+        }
+    }
+
+    BatchLookupResponse {
+        timestamp: data.timestamp.clone(),
+        count: validators.len(),
+        validators,
+        not_found_ids,
+        not_found_node_ids,
     }
 }
 
-#[get("/api/validators/top")]
-async fn get_top_validators(
+#[utoipa::path(
+    tag = "validators",
+    request_body = BatchLookupRequest,
+    responses(
+        (status = 200, description = "The validators found and the keys that matched none", body = Timestamped<BatchLookupResponse>),
+        (status = 400, description = "More than 500 ids and node ids together"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[post("/api/validators/batch")]
+async fn batch_validators(
     state: web::Data<Arc<AppState>>,
-    query: web::Query<std::collections::HashMap<String, String>>
+    body: web::Json<BatchLookupRequest>,
 ) -> impl Responder {
-    // top 50
-    let limit = query.get("limit").and_then(|l| l.parse::<usize>().ok()).unwrap_or(50);
+    let BatchLookupRequest { ids, node_ids } = body.into_inner();
+    if ids.len() + node_ids.len() > MAX_BATCH_LOOKUP {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} ids and node ids can be looked up per request", MAX_BATCH_LOOKUP)
+        }));
+    }
 
     match fetch_validator_data(&state).await {
-        Ok(data) => {
-            let count = std::cmp::min(limit, data.eligible_nodes.len());
-            HttpResponse::Ok().json(ValidatorsListResponse {
-                timestamp: data.timestamp,
-                count,
-                validators: data.eligible_nodes.into_iter().take(limit).collect(),
-            })
-        },
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to fetch top validators"
-        })),
+        Ok((data, cache_status)) => HttpResponse::Ok()
+            .insert_header(cache_status.header())
+            .json(timestamped(&data.timestamp, batch_lookup(&data, &ids, &node_ids))),
+        Err(e) => e.error_response(),
     }
 }
 
-#[get("/api/validators/{id}")]
-async fn get_validator_by_id(
+// The stats kept with the cached data, unless the cache has moved on since
+// `data` was fetched
+fn cached_stats(state: &AppState, data: &Arc<ValidatorResponse>, exclude_outliers: bool) -> Arc<StatsResponse> {
+    match &*state.cache.read() {
+        Some(cached) if Arc::ptr_eq(&cached.data, data) => cached.stats(exclude_outliers),
+        _ if exclude_outliers => Arc::new(compute_stats(data, &rate_outliers(&data.eligible_nodes))),
+        _ => Arc::new(compute_stats(data, &[])),
+    }
+}
+
+fn cached_outliers(state: &AppState, data: &Arc<ValidatorResponse>) -> Vec<u32> {
+    match &*state.cache.read() {
+        Some(cached) if Arc::ptr_eq(&cached.data, data) => cached.outliers.clone(),
+        _ => rate_outliers(&data.eligible_nodes),
+    }
+}
+
+fn cached_content_hash(state: &AppState, data: &Arc<ValidatorResponse>) -> u64 {
+    match &*state.cache.read() {
+        Some(cached) if Arc::ptr_eq(&cached.data, data) => cached.content_hash,
+        _ => content_hash(data),
+    }
+}
+
+#[utoipa::path(
+    tag = "validators",
+    params(
+        ("exclude_outliers" = Option<bool>, Query, description = "Leave reward-rate outliers out of the rate and availability summaries"),
+    ),
+    responses(
+        (status = 200, description = "Aggregate statistics for dashboards", body = Timestamped<StatsResponse>),
+        (status = 400, description = "Invalid query parameter"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/stats")]
+async fn get_stats(
     state: web::Data<Arc<AppState>>,
-    path: web::Path<u32>,
+    query: web::Query<HashMap<String, String>>
 ) -> impl Responder {
-    let validator_id = path.into_inner();
+    let exclude_outliers = match query.get("exclude_outliers").map(|e| e.parse::<bool>()) {
+        None => false,
+        Some(Ok(exclude_outliers)) => exclude_outliers,
+        Some(Err(_)) => return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'exclude_outliers' must be 'true' or 'false'"
+        })),
+    };
 
     match fetch_validator_data(&state).await {
-        Ok(data) => {
-            let validator = data.eligible_nodes.iter()
-                .chain(data.ineligible_nodes.iter())
-                .find(|v| v.id == validator_id);
+        Ok((data, cache_status)) => {
+            let stats = cached_stats(&state, &data, exclude_outliers);
+            HttpResponse::Ok()
+                .insert_header(cache_status.header())
+                .json(timestamped(&data.timestamp, &*stats))
+        },
+        Err(e) => e.error_response(),
+    }
+}
+
+// The reward epoch the data belongs to, which is what the fetch timestamps
+// alone cannot say
+#[utoipa::path(
+    tag = "validators",
+    responses(
+        (status = 200, description = "Current reward epoch and when the data was refreshed", body = Timestamped<EpochResponse>),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable and nothing cached"),
+    )
+)]
+#[get("/api/epoch")]
+async fn get_epoch(state: web::Data<Arc<AppState>>) -> impl Responder {
+    match fetch_validator_data(&state).await {
+        Ok((data, cache_status)) => HttpResponse::Ok()
+            .insert_header(cache_status.header())
+            .json(timestamped(&data.timestamp, EpochResponse {
+                reward_epoch: data.reward_epoch,
+                last_refresh: data.timestamp.clone(),
+                age_secs: cache_age_secs(&state),
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            })),
+        Err(e) => e.error_response(),
+    }
+}
+
+// Read-only view of the cache for operators; never starts a refresh
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "Cache age and refresh state", body = CacheStatusResponse))
+)]
+#[get("/api/cache/status")]
+async fn get_cache_status(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let fetched_at = state.cache.read().as_ref().map(|cached| cached.fetched_at);
+    let age = fetched_at.map(|at| SystemTime::now().duration_since(at).unwrap_or_default());
+
+    let last_refresh = fetched_at.map(|at| DateTime::<Utc>::from(at).to_rfc3339());
+    let now = chrono::Utc::now().to_rfc3339();
+
+    HttpResponse::Ok().json(CacheStatusResponse {
+        populated: fetched_at.is_some(),
+        last_refresh: last_refresh.clone(),
+        age_secs: age.map(|age| age.as_secs()),
+        ttl_secs: state.cache_ttl.as_secs(),
+        expired: age.is_none_or(|age| age >= state.cache_ttl),
+        refresh_in_flight: state.refresh_in_flight.load(Ordering::Acquire),
+        last_refresh_failed: state.last_refresh_failed.load(Ordering::Acquire),
+        field_coverage: state.field_coverage.read().clone(),
+        timestamp: now.clone(),
+        data_timestamp: last_refresh,
+        served_timestamp: now,
+    })
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses(
+        (status = 200, description = "Cached data passes every invariant", body = Timestamped<SelfTestResponse>),
+        (status = 500, description = "Invariants violated", body = Timestamped<SelfTestResponse>),
+        (status = 503, description = "No data cached yet"),
+    )
+)]
+#[get("/api/selftest")]
+async fn selftest(state: web::Data<Arc<AppState>>) -> impl Responder {
+    // Only inspects what is already cached, never triggers a fetch
+    let cache_read = state.cache.read();
+    let Some(CachedData { data, .. }) = &*cache_read else {
+        return HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "No cached data to check yet"
+        }));
+    };
+
+    let violations = check_invariants(data, &state.criteria);
+    let report = timestamped(&data.timestamp, SelfTestResponse {
+        passed: violations.is_empty(),
+        violations,
+        timestamp: data.timestamp.clone(),
+    });
+
+    if report.body.passed {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::InternalServerError().json(report)
+    }
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "Upstream schema drift report", body = DiagnosticsResponse))
+)]
+#[get("/api/diagnostics")]
+async fn diagnostics(state: web::Data<Arc<AppState>>) -> impl Responder {
+    HttpResponse::Ok().json(DiagnosticsResponse {
+        schema_diagnostics: state.schema_diagnostics,
+        schema: state.schema_report.read().clone(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+// Requests a single entity directly, bypassing the validator cache
+async fn probe_upstream(state: &AppState, timeout: Duration) -> UpstreamProbe {
+    let url = format!("{}/entity?limit=1&offset=0", state.flare_api);
+    let started = Instant::now();
+    let result = state.http_client.get(&url)
+        .timeout(timeout)
+        .send()
+        .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            UpstreamProbe {
+                reachable: status.is_success(),
+                status_code: Some(status.as_u16()),
+                latency_ms,
+                error: (!status.is_success()).then(|| format!("Upstream returned {}", status)),
+            }
+        },
+        Err(e) => {
+            let error = if e.is_timeout() {
+                format!("No response within {}s", timeout.as_secs_f64())
+            } else {
+                e.to_string()
+            };
+            UpstreamProbe { reachable: false, status_code: None, latency_ms, error: Some(error) }
+        },
+    }
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses(
+        (status = 200, description = "Upstream reachability and latency", body = UpstreamPingResponse),
+        (status = 429, description = "Probed too recently, see Retry-After"),
+    )
+)]
+#[get("/api/upstream/ping")]
+async fn upstream_ping(state: web::Data<Arc<AppState>>) -> impl Responder {
+    // Allow one probe per interval across all clients
+    {
+        let mut last_ping = state.last_upstream_ping.lock();
+        if let Some(last) = *last_ping {
+            let min_interval = Duration::from_secs(UPSTREAM_PING_MIN_INTERVAL_SECS);
+            let elapsed = last.elapsed();
+            if elapsed < min_interval {
+                let retry_after = (min_interval - elapsed).as_secs().max(1);
+                return HttpResponse::TooManyRequests()
+                    .insert_header(("Retry-After", retry_after.to_string()))
+                    .json(serde_json::json!({
+                        "error": "Upstream ping is rate limited, try again shortly"
+                    }));
+            }
+        }
+        *last_ping = Some(Instant::now());
+    }
+
+    HttpResponse::Ok().json(UpstreamPingResponse {
+        probe: probe_upstream(&state, Duration::from_secs(UPSTREAM_PING_TIMEOUT_SECS)).await,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+// Quotes a CSV field when it contains a delimiter, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// One row per validator in the given order, with nested fields flattened into
+// dotted columns; missing values are left empty. Only ineligible validators
+// carry unmet_criteria, which is what the eligible column reports.
+fn render_validators_csv(validators: &[&Validator]) -> String {
+    fn opt<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    let mut out = String::from(
+        "id,name,eligible,node_id,node_ids,delegation_address,\
+         conditions.ftso_anchor_feeds,conditions.ftso_block_latency_feeds,conditions.fdc,\
+         conditions.staking,conditions.passes,conditions.eligible_for_reward,\
+         provider_stats.primary,provider_stats.secondary,provider_stats.availability,provider_stats.active,\
+         reward_rates.wnat,reward_rates.mirror,reward_rates.pure,reward_rates.combined,reward_rates.apy,\
+         reward_rates.combined_avg,rate_vs_median,unmet_criteria\n",
+    );
+
+    for &v in validators {
+        let eligible = v.unmet_criteria.is_none();
+        let c = v.conditions.as_ref();
+        let p = v.provider_stats.as_ref();
+        let r = v.reward_rates.as_ref();
+        let row = [
+            v.id.to_string(),
+            v.name.clone(),
+            eligible.to_string(),
+            opt(v.node_id.as_ref()),
+            // Multiple nodes share one cell so the row shape stays fixed
+            v.node_ids.join(";"),
+            opt(v.delegation_address.as_ref()),
+            opt(c.map(|c| c.ftso_anchor_feeds)),
+            opt(c.map(|c| c.ftso_block_latency_feeds)),
+            opt(c.map(|c| c.fdc)),
+            opt(c.map(|c| c.staking)),
+            opt(c.map(|c| c.passes)),
+            opt(c.map(|c| c.eligible_for_reward)),
+            opt(p.and_then(|p| p.primary)),
+            opt(p.and_then(|p| p.secondary)),
+            opt(p.and_then(|p| p.availability)),
+            opt(p.and_then(|p| p.active)),
+            opt(r.map(|r| r.wnat)),
+            opt(r.map(|r| r.mirror)),
+            opt(r.map(|r| r.pure)),
+            opt(r.map(|r| r.combined)),
+            opt(r.map(|r| r.apy)),
+            opt(r.map(|r| r.combined_avg)),
+            opt(v.rate_vs_median),
+            opt(v.unmet_criteria),
+        ];
+        let row: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+// Identifies the validator data itself. The fetch timestamp is left out, so a
+// refresh that changes nothing keeps the same ETag.
+fn content_hash(data: &ValidatorResponse) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    match serde_json::to_vec(&(&data.eligible_nodes, &data.ineligible_nodes)) {
+        Ok(bytes) => bytes.hash(&mut hasher),
+        // Never matches a later hash, so clients just get a full response
+        Err(_) => SystemTime::now().hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+// The path, query string and Accept header pick the representation (flat or
+// /api/v2 envelope, page, filters, CSV), so they are part of the tag. Weak
+// because the body also carries served_timestamp, which differs on every request.
+fn list_etag(content_hash: u64, req: &actix_web::HttpRequest) -> String {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    req.path().hash(&mut hasher);
+    req.query_string().hash(&mut hasher);
+    req.headers().get(actix_web::http::header::ACCEPT).map(|a| a.as_bytes()).hash(&mut hasher);
+    format!("W/\"{:016x}-{:016x}\"", content_hash, hasher.finish())
+}
+
+// A page built by one of the list handlers, before it is rendered in the shape
+// of the route that asked for it
+struct ListPage<T> {
+    body: T,
+    // Applied to every validator in the body, see FieldMask
+    fields: Option<FieldMask>,
+    format: ResponseFormat,
+    pagination: Pagination,
+    data_timestamp: String,
+    cache_status: CacheStatus,
+    etag: String,
+}
+
+enum ListOutcome<T> {
+    Page(ListPage<T>),
+    // Responses that are the same in either shape: 304 and CSV
+    Rendered(HttpResponse),
+}
+
+enum ListError {
+    BadRequest(String),
+    NotAcceptable(String),
+    App(AppError),
+}
+
+impl From<AppError> for ListError {
+    fn from(e: AppError) -> Self {
+        ListError::App(e)
+    }
+}
+
+// A list body as is, or reduced to what a ?fields= mask keeps
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Masked<T> {
+    Full(T),
+    Sparse(serde_json::Value),
+}
+
+fn mask_validators<T: Serialize>(body: T, fields: Option<&FieldMask>) -> Masked<T> {
+    let Some(mask) = fields else {
+        return Masked::Full(body);
+    };
+    match serde_json::to_value(&body) {
+        Ok(mut value) => {
+            for key in ["validators", "eligible_nodes", "ineligible_nodes"] {
+                if let Some(list) = value.get_mut(key) {
+                    mask.apply(list);
+                }
+            }
+            Masked::Sparse(value)
+        },
+        Err(e) => {
+            log::warn!("Could not apply the field mask, sending full records: {}", e);
+            Masked::Full(body)
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListShape {
+    // Body fields at the top level, as the unversioned routes have always returned
+    Flat,
+    // ApiEnvelope<T>, served under /api/v2
+    Enveloped,
+}
+
+fn render_list<T: Serialize>(
+    shape: ListShape,
+    cache_age_secs: Option<u64>,
+    result: Result<ListOutcome<T>, ListError>,
+) -> HttpResponse {
+    let error_envelope = |message: String| ApiEnvelope::<serde_json::Value> {
+        data: None,
+        meta: EnvelopeMeta {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            data_timestamp: None,
+            cache_age_secs,
+            cache_status: None,
+            pagination: None,
+        },
+        errors: vec![message],
+    };
+
+    let response = match (result, shape) {
+        (Ok(ListOutcome::Rendered(response)), _) => response,
+        // CSV pages come back Rendered, so only structured formats get here
+        (Ok(ListOutcome::Page(page)), ListShape::Flat) => {
+            let mut builder = HttpResponse::Ok();
+            builder
+                .insert_header(page.cache_status.header())
+                .insert_header((actix_web::http::header::ETAG, page.etag));
+            formatted(builder, page.format, &timestamped(&page.data_timestamp, Paginated {
+                body: mask_validators(page.body, page.fields.as_ref()),
+                pagination: page.pagination,
+            }), || None)
+        },
+        (Ok(ListOutcome::Page(page)), ListShape::Enveloped) => {
+            let mut builder = HttpResponse::Ok();
+            builder
+                .insert_header(page.cache_status.header())
+                .insert_header((actix_web::http::header::ETAG, page.etag));
+            formatted(builder, page.format, &ApiEnvelope {
+                data: Some(mask_validators(page.body, page.fields.as_ref())),
+                meta: EnvelopeMeta {
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                    data_timestamp: Some(page.data_timestamp),
+                    cache_age_secs,
+                    cache_status: Some(page.cache_status.header().1.to_string()),
+                    pagination: Some(page.pagination),
+                },
+                errors: Vec::new(),
+            }, || None)
+        },
+        (Err(ListError::BadRequest(e)), ListShape::Flat) => {
+            HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))
+        },
+        (Err(ListError::BadRequest(e)), ListShape::Enveloped) => {
+            HttpResponse::BadRequest().json(error_envelope(e))
+        },
+        (Err(ListError::NotAcceptable(e)), ListShape::Flat) => not_acceptable(e),
+        (Err(ListError::NotAcceptable(e)), ListShape::Enveloped) => {
+            HttpResponse::NotAcceptable().json(error_envelope(e))
+        },
+        (Err(ListError::App(e)), ListShape::Flat) => e.error_response(),
+        // Keeps the status and Retry-After of the flat error
+        (Err(ListError::App(e)), ListShape::Enveloped) => match serde_json::to_vec(&error_envelope(e.to_string())) {
+            Ok(body) => e.error_response().set_body(body).map_into_boxed_body(),
+            Err(_) => e.error_response(),
+        },
+    };
+    vary_on_accept(response)
+}
+
+// 304 with no body when If-None-Match already names this ETag
+fn not_modified(req: &actix_web::HttpRequest, etag: &str, cache_status: CacheStatus) -> Option<HttpResponse> {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let matches = req.headers().get_all(actix_web::http::header::IF_NONE_MATCH)
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag));
+
+    matches.then(|| HttpResponse::NotModified()
+        .insert_header(cache_status.header())
+        .insert_header((actix_web::http::header::ETAG, etag.to_string()))
+        .finish())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseFormat {
+    Json,
+    Yaml,
+    Csv,
+    MessagePack,
+}
+
+impl ResponseFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" => Some(ResponseFormat::Json),
+            "yaml" | "yml" => Some(ResponseFormat::Yaml),
+            "csv" => Some(ResponseFormat::Csv),
+            "msgpack" | "messagepack" => Some(ResponseFormat::MessagePack),
+            _ => None,
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.trim().to_ascii_lowercase().as_str() {
+            "application/json" | "application/*" | "*/*" => Some(ResponseFormat::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Some(ResponseFormat::Yaml),
+            "text/csv" => Some(ResponseFormat::Csv),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(ResponseFormat::MessagePack)
+            },
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ResponseFormat::Json => "application/json",
+            ResponseFormat::Yaml => "application/yaml",
+            ResponseFormat::Csv => "text/csv; charset=utf-8",
+            ResponseFormat::MessagePack => "application/msgpack",
+        }
+    }
+
+    // ?format= wins over the Accept header, which is read by q-value with ties
+    // going to the earlier type. JSON when neither asks for anything; an error
+    // (answered with 406) when they only name formats we cannot produce.
+    fn negotiate(req: &actix_web::HttpRequest, query: &HashMap<String, String>) -> Result<Self, String> {
+        if let Some(name) = query.get("format") {
+            return Self::from_name(name).ok_or_else(|| format!(
+                "Unsupported format '{}', expected json, yaml, csv or msgpack", name
+            ));
+        }
+
+        let Some(accept) = req.headers().get(actix_web::http::header::ACCEPT) else {
+            return Ok(ResponseFormat::Json);
+        };
+        let accept = accept.to_str().map_err(|_| "Unreadable Accept header".to_string())?;
+        let mut best: Option<(f64, Self)> = None;
+        for range in accept.split(',').filter(|r| !r.trim().is_empty()) {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or_default();
+            let q = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f64>().ok())
+                .unwrap_or(1.0);
+            let Some(format) = Self::from_media_type(media_type).filter(|_| q > 0.0) else {
+                continue;
+            };
+            if best.is_none_or(|(best_q, _)| q > best_q) {
+                best = Some((q, format));
+            }
+        }
+        match best {
+            Some((_, format)) => Ok(format),
+            // An empty header asks for nothing in particular
+            None if accept.trim().is_empty() => Ok(ResponseFormat::Json),
+            None => Err(format!(
+                "None of '{}' can be produced, expected application/json, application/yaml, text/csv or application/msgpack",
+                accept
+            )),
+        }
+    }
+}
+
+// Every route that negotiates its format says so, or a shared cache could hand
+// one client's CSV or MessagePack to the next client asking for JSON
+fn vary_on_accept(mut response: HttpResponse) -> HttpResponse {
+    response.headers_mut().append(
+        actix_web::http::header::VARY,
+        actix_web::http::header::HeaderValue::from_static("Accept"),
+    );
+    response
+}
+
+fn not_acceptable(message: String) -> HttpResponse {
+    HttpResponse::NotAcceptable().json(serde_json::json!({ "error": message }))
+}
+
+// Serializes body in the negotiated format. CSV has a fixed column layout, so
+// only bodies that are validator rows have one, and csv returns None otherwise.
+fn formatted<T: Serialize>(
+    mut builder: actix_web::HttpResponseBuilder,
+    format: ResponseFormat,
+    body: &T,
+    csv: impl FnOnce() -> Option<String>,
+) -> HttpResponse {
+    let encoded = match format {
+        ResponseFormat::Json => return builder.json(body),
+        ResponseFormat::Csv => match csv() {
+            Some(csv) => Ok(csv.into_bytes()),
+            None => return not_acceptable("This response has no CSV form".to_string()),
+        },
+        ResponseFormat::Yaml => serde_yaml::to_string(body)
+            .map(String::into_bytes)
+            .map_err(|e| e.to_string()),
+        ResponseFormat::MessagePack => rmp_serde::to_vec_named(body).map_err(|e| e.to_string()),
+    };
+
+    match encoded {
+        Ok(bytes) => builder.content_type(format.content_type()).body(bytes),
+        Err(e) => {
+            log::error!("Could not encode the response as {:?}: {}", format, e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Could not encode the response"
+            }))
+        },
+    }
+}
+
+// The CSV form of a list page: the same rows the JSON page would hold
+fn csv_response(cache_status: CacheStatus, etag: String, validators: &[&Validator]) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(cache_status.header())
+        .insert_header((actix_web::http::header::ETAG, etag))
+        .content_type(ResponseFormat::Csv.content_type())
+        .body(render_validators_csv(validators))
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn render_validator_metrics(data: &ValidatorResponse, limit: usize) -> String {
+    use std::fmt::Write;
+
+    let validators: Vec<(&Validator, bool)> = data.eligible_nodes.iter().map(|v| (v, true))
+        .chain(data.ineligible_nodes.iter().map(|v| (v, false)))
+        .take(limit)
+        .collect();
+    let labels = |v: &Validator| format!("id=\"{}\",name=\"{}\"", v.id, escape_label(&v.name));
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# HELP flare_validator_eligible Whether the validator meets the eligibility criteria (1) or not (0)");
+    let _ = writeln!(out, "# TYPE flare_validator_eligible gauge");
+    for (v, eligible) in &validators {
+        let _ = writeln!(out, "flare_validator_eligible{{{}}} {}", labels(v), u8::from(*eligible));
+    }
+
+    let _ = writeln!(out, "# HELP flare_validator_combined_reward_rate Combined per-epoch reward rate as a fraction");
+    let _ = writeln!(out, "# TYPE flare_validator_combined_reward_rate gauge");
+    for (v, _) in &validators {
+        if let Some(rates) = &v.reward_rates {
+            let _ = writeln!(out, "flare_validator_combined_reward_rate{{{}}} {}", labels(v), rates.combined);
+        }
+    }
+
+    let _ = writeln!(out, "# HELP flare_validator_availability Provider availability as a fraction");
+    let _ = writeln!(out, "# TYPE flare_validator_availability gauge");
+    for (v, _) in &validators {
+        if let Some(availability) = v.provider_stats.as_ref().and_then(|p| p.availability) {
+            let _ = writeln!(out, "flare_validator_availability{{{}}} {}", labels(v), availability);
+        }
+    }
+
+    out
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses((status = 200, description = "Prometheus service metrics", body = String, content_type = "text/plain"))
+)]
+#[get("/metrics")]
+async fn metrics(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let mut body = state.metrics.render();
+    state.sse_subscribers.render(&mut body);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses(
+        (status = 200, description = "Per-validator Prometheus gauges", body = String, content_type = "text/plain"),
+        (status = 404, description = "VALIDATOR_METRICS is not enabled"),
+    )
+)]
+#[get("/metrics/validators")]
+async fn validator_metrics(state: web::Data<Arc<AppState>>) -> impl Responder {
+    let Some(limit) = state.validator_metrics_limit else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Per-validator metrics are disabled, set VALIDATOR_METRICS=true to enable"
+        }));
+    };
+
+    // Scrapes read whatever is cached and never trigger an upstream fetch
+    let body = match &*state.cache.read() {
+        Some(cached) => render_validator_metrics(&cached.data, limit),
+        None => String::new(),
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+// Compares every byte regardless of where the first mismatch is, so response
+// timing doesn't reveal how much of the key was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn authorized(req: &actix_web::HttpRequest, key: &str) -> bool {
+    req.headers().get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), key.as_bytes()))
+}
+
+#[utoipa::path(
+    tag = "service",
+    responses(
+        (status = 200, description = "Cache refreshed", body = Timestamped<RefreshResponse>),
+        (status = 401, description = "Missing or invalid API key when REFRESH_API_KEY is set"),
+        (status = 409, description = "A refresh is already in progress"),
+        (status = 502, description = "Upstream sent a response that could not be used"),
+        (status = 503, description = "Upstream unreachable"),
+    )
+)]
+#[post("/api/refresh")]
+async fn force_refresh(req: actix_web::HttpRequest, state: web::Data<Arc<AppState>>) -> impl Responder {
+    if let Some(key) = &state.refresh_api_key {
+        if !authorized(&req, key) {
+            return HttpResponse::Unauthorized()
+                .insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Bearer"))
+                .json(serde_json::json!({ "error": "Missing or invalid API key" }));
+        }
+    }
+
+    let Some(_guard) = try_begin_refresh(&state) else {
+        return HttpResponse::Conflict().json(serde_json::json!({
+            "error": "A refresh is already in progress"
+        }));
+    };
+
+    // Fetch fresh data, the old cache keeps being served until it lands
+    match refresh_validator_data(&state).await {
+        Ok(data) => HttpResponse::Ok().json(timestamped(&data.timestamp, RefreshResponse {
+            success: true,
+            message: "Cache refreshed successfully".to_string(),
+            timestamp: data.timestamp.clone(),
+        })),
+        Err(e) => AppError::from_fetch(&e, state.cache_ttl).error_response(),
+    }
+}
+
+// Accepts absolute http(s) URLs; the trailing slash is dropped so paths can be appended
+fn parse_flare_api_url(raw: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(raw).map_err(|e| format!("invalid FLARE_API_URL {:?}: {}", raw, e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!("invalid FLARE_API_URL {:?}: expected an http(s) URL with a host", raw));
+    }
+    Ok(raw.trim_end_matches('/').to_string())
+}
 
-            match validator {
-                Some(v) => HttpResponse::Ok().json(v),
-                None => HttpResponse::NotFound().json(serde_json::json!({
-                    "error": "Validator not found"
-                })),
+// LOG_LEVEL sets the default level and RUST_LOG-style directives refine it
+// per module, e.g. LOG_LEVEL=warn with RUST_LOG=flare_validator_api=debug.
+// An invalid half is dropped with a warning and the other half still applies.
+fn log_filter(level: Option<&str>, directives: Option<&str>) -> (String, Vec<String>) {
+    let mut warnings = Vec::new();
+    let level = match level.map(str::trim).filter(|l| !l.is_empty()) {
+        Some(level) if level.parse::<tracing_subscriber::filter::LevelFilter>().is_ok() => level,
+        Some(level) => {
+            warnings.push(format!(
+                "Ignoring invalid LOG_LEVEL {:?}, expected trace, debug, info, warn, error or off; using info",
+                level
+            ));
+            "info"
+        },
+        None => "info",
+    };
+
+    let spec = match directives.map(str::trim).filter(|d| !d.is_empty()) {
+        Some(directives) => {
+            let spec = format!("{},{}", level, directives);
+            match tracing_subscriber::EnvFilter::try_new(&spec) {
+                Ok(_) => spec,
+                Err(e) => {
+                    warnings.push(format!("Ignoring invalid RUST_LOG {:?}: {}", directives, e));
+                    level.to_string()
+                },
             }
         },
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to fetch validator details"
-        })),
-    }
+        None => level.to_string(),
+    };
+    (spec, warnings)
 }
 
-#[post("/api/refresh")]
-async fn force_refresh(state: web::Data<Arc<AppState>>) -> impl Responder {
-    // Clear the cache
-    {
-        let mut cache_write = state.cache.write();
-        *cache_write = None;
+// Human-readable logs by default, one JSON object per line with LOG_FORMAT=json.
+// log:: records from this crate and its dependencies are forwarded into tracing.
+fn init_logging() {
+    let (spec, warnings) = log_filter(
+        std::env::var("LOG_LEVEL").ok().as_deref(),
+        std::env::var("RUST_LOG").ok().as_deref(),
+    );
+    // Closing the per-request span logs one line per request with its latency
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::new(&spec))
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE);
+
+    match std::env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => builder.json().init(),
+        _ => builder.init(),
     }
 
-    // Fetch fresh data
-    match fetch_validator_data(&state).await {
-        Ok(data) => HttpResponse::Ok().json(RefreshResponse {
-            success: true,
-            message: "Cache refreshed successfully".to_string(),
-            timestamp: data.timestamp,
-        }),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to refresh cache"
-        })),
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
+    tracing::info!("Log filter: {}", spec);
+}
+
+// BIND_ADDR must be an IP (v4 or v6, no brackets) and PORT a port number
+fn parse_bind_addr(host: &str, port: &str) -> Result<std::net::SocketAddr, String> {
+    let ip: std::net::IpAddr = host.trim().parse()
+        .map_err(|_| format!("invalid BIND_ADDR {:?}: expected an IP address such as 0.0.0.0 or 127.0.0.1", host))?;
+    let port: u16 = port.trim().parse()
+        .map_err(|_| format!("invalid PORT {:?}: expected a number from 0 to 65535", port))?;
+    Ok(std::net::SocketAddr::new(ip, port))
+}
+
+// WORKERS overrides num_cpus::get(), which counts host cores even when a
+// container's CPU limit allows far fewer
+fn parse_workers(raw: &str) -> Result<usize, String> {
+    match raw.trim().parse::<usize>() {
+        Ok(workers) if workers > 0 => Ok(workers),
+        _ => Err(format!("invalid WORKERS {:?}: expected a positive integer", raw)),
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    init_logging();
+
+    let network = match std::env::var("NETWORK").map(|n| n.parse::<Network>()) {
+        Err(_) => Network::Flare,
+        Ok(Ok(network)) => network,
+        Ok(Err(e)) => {
+            log::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        },
+    };
+    let flare_api = std::env::var("FLARE_API_URL").unwrap_or_else(|_| network.default_api_url().to_string());
+    let flare_api = match parse_flare_api_url(&flare_api) {
+        Ok(url) => url,
+        Err(e) => {
+            log::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        },
+    };
+    // Checked up front so a typo fails fast instead of after the first fetch
+    let addr = match parse_bind_addr(
+        &std::env::var("BIND_ADDR").unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string()),
+        &std::env::var("PORT").unwrap_or_else(|_| DEFAULT_PORT.to_string()),
+    ) {
+        Ok(addr) => addr,
+        Err(e) => {
+            log::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        },
+    };
+    let workers = match std::env::var("WORKERS").map(|w| parse_workers(&w)) {
+        Err(_) => num_cpus::get(),
+        Ok(Ok(workers)) => workers,
+        Ok(Err(e)) => {
+            log::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        },
+    };
+    let trusted_proxies = match parse_trusted_proxies(&std::env::var("TRUSTED_PROXIES").unwrap_or_default()) {
+        Ok(networks) => TrustedProxies { networks, any_peer: env_or("TRUST_FORWARDED_FOR", false) },
+        Err(e) => {
+            log::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        },
+    };
+    let sse_full_policy = match std::env::var("SSE_FULL_POLICY").map(|p| p.parse::<SseFullPolicy>()) {
+        Err(_) => SseFullPolicy::Reject,
+        Ok(Ok(policy)) => policy,
+        Ok(Err(e)) => {
+            log::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        },
+    };
+    if trusted_proxies.any_peer {
+        log::warn!("TRUST_FORWARDED_FOR is deprecated and believes X-Forwarded-For from any peer, list your proxies in TRUSTED_PROXIES instead");
+    }
+    if trusted_proxies.networks.is_empty() {
+        log::info!("No TRUSTED_PROXIES, ignoring X-Forwarded-For and X-Forwarded-Proto");
+    } else {
+        let networks: Vec<String> = trusted_proxies.networks.iter().map(|net| net.to_string()).collect();
+        log::info!("Believing X-Forwarded-For and X-Forwarded-Proto from {}", networks.join(", "));
+    }
+    let cache_ttl = Duration::from_secs(env_or("CACHE_TTL_SECS", DEFAULT_CACHE_TTL_SECS).max(1));
+    log::info!("Using {} explorer API at {} with a {}s cache TTL", network.name(), flare_api, cache_ttl.as_secs());
 
+    let http_timeout = Duration::from_secs(env_or("HTTP_TIMEOUT_SECS", DEFAULT_HTTP_TIMEOUT_SECS).max(1));
+    let connect_timeout = Duration::from_secs(env_or("HTTP_CONNECT_TIMEOUT_SECS", DEFAULT_HTTP_CONNECT_TIMEOUT_SECS).max(1));
+    log::info!(
+        "Upstream HTTP timeouts: {}s total, {}s connect",
+        http_timeout.as_secs(), connect_timeout.as_secs()
+    );
+    let requested_page_size = env_or("UPSTREAM_PAGE_SIZE", DEFAULT_UPSTREAM_PAGE_SIZE);
+    let upstream_page_size = clamp_page_size(requested_page_size);
+    if upstream_page_size != requested_page_size {
+        log::warn!(
+            "UPSTREAM_PAGE_SIZE={} is outside 1..={}, using {}",
+            requested_page_size, MAX_UPSTREAM_PAGE_SIZE, upstream_page_size
+        );
+    }
+    let upstream_max_entities = env_opt("UPSTREAM_MAX_ENTITIES").filter(|&max: &usize| max > 0);
+    log::info!(
+        "Fetching upstream in pages of {} entities, {}",
+        upstream_page_size,
+        upstream_max_entities.map_or("no total cap".to_string(), |max| format!("at most {} in total", max))
+    );
+    // Idle connections are kept for reuse across refreshes; 0 disables the
+    // idle timeout or TCP keepalive respectively
+    let pool_max_idle = env_or("HTTP_POOL_MAX_IDLE_PER_HOST", DEFAULT_HTTP_POOL_MAX_IDLE);
+    let pool_idle_timeout = Some(env_or("HTTP_POOL_IDLE_TIMEOUT_SECS", DEFAULT_HTTP_POOL_IDLE_TIMEOUT_SECS))
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs);
+    let tcp_keepalive = Some(env_or("HTTP_TCP_KEEPALIVE_SECS", DEFAULT_HTTP_TCP_KEEPALIVE_SECS))
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs);
+    log::info!(
+        "Upstream connection pool: up to {} idle per host, idle timeout {}, TCP keepalive {}",
+        pool_max_idle,
+        pool_idle_timeout.map_or("off".to_string(), |t| format!("{}s", t.as_secs())),
+        tcp_keepalive.map_or("off".to_string(), |t| format!("{}s", t.as_secs()))
+    );
     let http_client = Client::builder()
-        .timeout(Duration::from_secs(10))
+        .timeout(http_timeout)
+        .connect_timeout(connect_timeout)
+        .pool_max_idle_per_host(pool_max_idle)
+        .pool_idle_timeout(pool_idle_timeout)
+        .tcp_keepalive(tcp_keepalive)
         .build()
         .expect("Failed to create HTTP client");
 
     let state = Arc::new(AppState {
         http_client,
+        network,
+        flare_api,
+        cache_ttl,
         cache: PLRwLock::new(None),
+        history: PLRwLock::new(VecDeque::with_capacity(HISTORY_SIZE)),
+        epochs_per_year: env_or("EPOCHS_PER_YEAR", DEFAULT_EPOCHS_PER_YEAR),
+        availability_scale: env_opt("AVAILABILITY_SCALE")
+            .filter(|scale: &f64| *scale > 0.0)
+            .unwrap_or(DEFAULT_AVAILABILITY_SCALE),
+        eligible_list_cap: env_opt("ELIGIBLE_LIST_CAP"),
+        last_upstream_ping: PLMutex::new(None),
+        wrap_responses: std::env::var("RESPONSE_ENVELOPE").is_ok_and(|v| v == "wrapped"),
+        validator_metrics_limit: env_or("VALIDATOR_METRICS", false)
+            .then(|| env_or("VALIDATOR_METRICS_LIMIT", DEFAULT_VALIDATOR_METRICS_LIMIT)),
+        body_timeout: Duration::from_secs(env_or("UPSTREAM_BODY_TIMEOUT_SECS", DEFAULT_BODY_TIMEOUT_SECS)),
+        field_aliases: parse_field_aliases(&std::env::var("FIELD_ALIASES").unwrap_or_default()),
+        schema_diagnostics: env_or("SCHEMA_DIAGNOSTICS", false),
+        schema_report: PLRwLock::new(None),
+        field_coverage: PLRwLock::new(None),
+        criteria: EligibilityCriteria::from_env(),
+        delegation_url_template: std::env::var("DELEGATION_URL_TEMPLATE").ok(),
+        reward_avg_window: env_or("REWARD_AVG_WINDOW", DEFAULT_REWARD_AVG_WINDOW).clamp(1, HISTORY_SIZE),
+        dedup_entities: env_or("DEDUP_ENTITIES", false),
+        warm_derived_views: env_or("WARM_DERIVED_VIEWS", true),
+        refresh_in_flight: AtomicBool::new(false),
+        refresh_finished: tokio::sync::Notify::new(),
+        last_refresh_failed: AtomicBool::new(false),
+        last_refresh_error: PLMutex::new(None),
+        ready: AtomicBool::new(false),
+        max_retries: env_or("FLARE_MAX_RETRIES", DEFAULT_MAX_RETRIES),
+        request_deadline: Some(env_or("REQUEST_DEADLINE_MS", DEFAULT_REQUEST_DEADLINE_MS))
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis),
+        upstream_page_size,
+        upstream_max_entities,
+        metrics: Metrics::default(),
+        refresh_api_key: std::env::var("REFRESH_API_KEY").ok().filter(|k| !k.is_empty()),
+        rate_limiter: Some(env_or("RATE_LIMIT_PER_MIN", DEFAULT_RATE_LIMIT_PER_MIN))
+            .filter(|&per_min| per_min > 0)
+            .map(RateLimiter::new),
+        trusted_proxies,
+        cache_file: match std::env::var("CACHE_FILE") {
+            Ok(path) if path.is_empty() => None,
+            Ok(path) => Some(path.into()),
+            Err(_) => Some(DEFAULT_CACHE_FILE.into()),
+        },
+        refresh_events: tokio::sync::broadcast::channel(SUBSCRIBER_BUFFER).0,
+        sse_subscribers: Arc::new(SseSubscribers::new(
+            Some(env_or("SSE_MAX_SUBSCRIBERS", DEFAULT_SSE_MAX_SUBSCRIBERS)).filter(|&max| max > 0),
+            sse_full_policy,
+        )),
+        entity_cache: PLMutex::new(HashMap::new()),
+    });
+
+    let restored = state.cache_file.as_deref().and_then(|path| load_cache(path, state.cache_ttl));
+    if let Some((data, saved_at)) = restored {
+        log::info!(
+            "Restored cache from disk: {} eligible, {} ineligible",
+            data.eligible_count, data.ineligible_count
+        );
+        state.metrics.eligible_validators.store(data.eligible_count as u64, Ordering::Relaxed);
+        state.metrics.ineligible_validators.store(data.ineligible_count as u64, Ordering::Relaxed);
+        *state.cache.write() = Some(CachedData::new(data, saved_at, state.warm_derived_views));
+        state.ready.store(true, Ordering::Release);
+    }
+    let restored = state.cache.read().is_some();
+
+    // One real fetch before serving, so a wrong FLARE_API_URL or an upstream
+    // schema we cannot read stops the process instead of the first request
+    let startup_check = env_or("STARTUP_CHECK", true);
+    if startup_check {
+        let checked = match refresh_validator_data(&state).await {
+            Ok(data) => state.field_coverage.read().as_ref()
+                .map_or(Err("no field coverage was recorded".to_string()), check_startup_fetch)
+                .map(|()| data),
+            Err(e) => Err(format!("initial fetch from {} failed: {}", state.flare_api, e)),
+        };
+        match checked {
+            Ok(data) => log::info!(
+                "Startup check passed: {} eligible, {} ineligible",
+                data.eligible_count, data.ineligible_count
+            ),
+            Err(e) => {
+                log::error!("Startup check failed, exiting: {}", e);
+                return Err(std::io::Error::other(e));
+            },
+        }
+    } else if restored {
+        log::info!("STARTUP_CHECK disabled, serving the restored cache until the first background refresh");
+    } else {
+        log::warn!("STARTUP_CHECK disabled, nothing is cached until the first background refresh");
+    }
+
+    // Keep the cache fresh in the background so requests never wait on Flare
+    let refresh_state = Arc::clone(&state);
+    let refresh_task = actix_web::rt::spawn(async move {
+        let mut interval = tokio::time::interval(refresh_state.cache_ttl);
+        // The first tick fires immediately: skip it after the startup check,
+        // otherwise use it to replace restored data or fill the empty cache
+        if startup_check {
+            interval.tick().await;
+        }
+        loop {
+            interval.tick().await;
+            let Some(_guard) = try_begin_refresh(&refresh_state) else {
+                continue;
+            };
+            if let Err(e) = refresh_validator_data(&refresh_state).await {
+                log::warn!("Background refresh failed, serving previous data: {}", e);
+            }
+        }
     });
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
+    let allowed_origins = parse_allowed_origins(&std::env::var("ALLOWED_ORIGINS").unwrap_or_default());
+    if allowed_origins.is_empty() {
+        log::info!("CORS disabled, set ALLOWED_ORIGINS to allow browser clients on other origins");
+    } else {
+        log::info!("CORS enabled for {}", allowed_origins.join(", "));
+    }
+
     log::info!("Starting server at {}", addr);
 
     // Print usage on startup
@@ -415,28 +6854,1183 @@ async fn main() -> std::io::Result<()> {
     println!("Usage:");
     println!("  /                        - API usage information");
     println!("  /health                  - Health check endpoint");
+    println!("  /ready                   - Readiness probe (503 until data is cached)");
+    println!("  /health/deep             - Upstream reachability and cache state, ok or degraded");
     println!("  /api/validators          - List all validators");
+    println!("  /api/validators/stream   - All validators as NDJSON, one per line");
+    println!("  /api/validators/subscribe - Server-Sent Events on every refresh");
     println!("  /api/validators/eligible - List eligible validators");
     println!("  /api/validators/ineligible - List ineligible validators");
     println!("  /api/validators/top      - List top validators (default: 50)");
-    println!("  /api/validators/top?limit=N - List top N validators");
+    println!("  /api/validators/top?limit=N&offset=M - List N validators from rank M+1");
+    println!("  /api/v2/validators[/eligible|/ineligible|/top] - Same lists in a data/meta/errors envelope");
+    println!("  /api/validators/select?count=N&seed=S - Weighted random stake selection");
+    println!("  /api/validators/random?seed=S - One eligible validator (&weighted=true by reward)");
+    println!("  /api/validators/compare?ids=1,2,3 - Side-by-side view with eligible ranks");
+    println!("  /api/validators/search?q=NAME - Find validators by name (&fuzzy=true tolerates typos)");
+    println!("  /api/validators/diff-range?from=TS&to=TS - Eligibility changes between snapshots");
+    println!("  /api/validators/changes?since=TS - Validators changed since a previous poll");
+    println!("  /api/validators/by-node/{{node_id}} - Get validator by node ID");
+    println!("  /api/validators/by-delegation/{{address}} - Get validator by delegation address");
     println!("  /api/validators/{{id}}     - Get validator by ID");
+    println!("  /api/validators/{{id}}/history - Reward rate samples from recent refreshes");
+    println!("  /api/validators/{{id}}/delegation-url - Delegation address and wallet link");
+    println!("  /api/nodes/status        - Eligibility for a list of node ids (POST)");
+    println!("  /api/validators/batch    - Validators for a list of ids and node ids (POST)");
+    println!("  /api/select/drift        - Compare a delegation split with the recommended one (POST)");
+    println!("  /api/stats               - Aggregate statistics for dashboards");
+    println!("  /api/epoch               - Current reward epoch and last refresh time");
+    println!("  /api/cache/status        - Cache age and refresh status, never triggers a fetch");
+    println!("  /api/selftest            - Check invariants on the cached data");
+    println!("  /api/diagnostics         - Upstream schema drift report");
+    println!("  /api/upstream/ping       - Probe upstream reachability and latency");
+    println!("  /metrics                 - Prometheus service metrics");
+    println!("  /metrics/validators      - Per-validator Prometheus gauges (opt-in)");
     println!("  /api/refresh             - Force refresh cache (POST)");
+    println!("  /openapi.json            - OpenAPI spec for these endpoints");
+
+    log::info!(
+        "Starting {} HTTP workers{}",
+        workers,
+        if std::env::var("WORKERS").is_ok() { " (WORKERS)" } else { " (one per detected CPU)" }
+    );
+    let shutdown_timeout = env_or("SHUTDOWN_TIMEOUT_SECS", DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+    log::info!("On SIGINT/SIGTERM in-flight requests get up to {}s to finish", shutdown_timeout);
+    let shutdown_state = Arc::clone(&state);
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(Arc::clone(&state)))
+            .wrap(from_fn(rate_limit))
+            .wrap(from_fn(field_aliases))
+            .wrap(from_fn(response_envelope))
+            .wrap(from_fn(track_requests))
+            // Outside the body-rewriting middlewares, which need plain JSON
+            .wrap(Compress::default())
+            .wrap(Condition::new(!allowed_origins.is_empty(), build_cors(&allowed_origins)))
+            .wrap(from_fn(request_id_header))
+            .wrap(TracingLogger::<ClientSpan>::new())
             .service(usage)
             .service(health_check)
+            .service(readiness_check)
+            .service(deep_health_check)
             .service(get_all_validators)
+            .service(stream_validators)
+            .service(subscribe_validators)
             .service(get_eligible_validators)
             .service(get_ineligible_validators)
             .service(get_top_validators)
+            .service(get_all_validators_v2)
+            .service(get_eligible_validators_v2)
+            .service(get_ineligible_validators_v2)
+            .service(get_top_validators_v2)
+            .service(select_validators)
+            .service(random_validator)
+            .service(compare_validators)
+            .service(search_validators)
+            .service(get_diff_range)
+            .service(get_changes)
+            .service(get_validator_by_node)
+            .service(get_validator_by_delegation)
             .service(get_validator_by_id)
+            .service(get_validator_history)
+            .service(get_delegation_url)
+            .service(get_nodes_status)
+            .service(batch_validators)
+            .service(selection_drift_advice)
+            .service(get_stats)
+            .service(get_epoch)
+            .service(get_cache_status)
+            .service(selftest)
+            .service(diagnostics)
+            .service(upstream_ping)
+            .service(metrics)
+            .service(validator_metrics)
             .service(force_refresh)
+            .service(openapi_spec)
     })
-    .workers(num_cpus::get())
+    .workers(workers)
+    // SIGINT/SIGTERM stop accepting connections and give in-flight requests this long
+    .shutdown_timeout(shutdown_timeout)
     .bind(addr)?
     .run()
-    .await
+    .await?;
+
+    log::info!("HTTP server stopped, stopping background refresh");
+    refresh_task.abort();
+    let cached = shutdown_state.cache.read().as_ref().map(|c| (Arc::clone(&c.data), c.fetched_at));
+    if let (Some(path), Some((data, fetched_at))) = (&shutdown_state.cache_file, cached) {
+        log::info!("Flushing cache to {}", path.display());
+        save_cache(path, &data, fetched_at).await;
+    }
+    log::info!("Shutdown complete");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(value: serde_json::Value) -> FlareEntity {
+        serde_json::from_value(value).expect("entity should deserialize")
+    }
+
+    // Mirrors a page of the Flare /entity endpoint, including fields we don't read
+    const FIXTURE: &str = include_str!("../tests/fixtures/flare_entities.json");
+
+    fn fixture_entities() -> Vec<FlareEntity> {
+        serde_json::from_str::<FlareEntityList>(FIXTURE)
+            .expect("fixture should deserialize as a Flare entity page")
+            .results
+    }
+
+    fn build(criteria: &EligibilityCriteria) -> ValidatorResponse {
+        build_validator_response(
+            &fixture_entities(),
+            criteria,
+            DEFAULT_EPOCHS_PER_YEAR,
+            DEFAULT_AVAILABILITY_SCALE,
+            &VecDeque::new(),
+            DEFAULT_REWARD_AVG_WINDOW,
+            false,
+        )
+    }
+
+    fn find(data: &ValidatorResponse, id: u32) -> &Validator {
+        data.eligible_nodes.iter()
+            .chain(&data.ineligible_nodes)
+            .find(|v| v.id == id)
+            .unwrap_or_else(|| panic!("validator {} missing", id))
+    }
+
+    #[test]
+    fn fixture_deserializes() {
+        let entities = fixture_entities();
+        assert_eq!(entities.len(), 9);
+        assert!(entities[8].entityminimalconditions.is_none());
+    }
+
+    #[test]
+    fn process_entity_maps_fields() {
+        let entities = fixture_entities();
+        let v = process_entity(&entities[0], DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE);
+
+        assert_eq!(v.id, 1);
+        assert_eq!(v.name, "Eligible Low");
+        assert_eq!(v.node_ids, vec!["NodeID-FixtureOneA", "NodeID-FixtureOneB"]);
+        assert_eq!(v.node_id.as_deref(), Some("NodeID-FixtureOneA"));
+        assert_eq!(v.delegation_address.as_deref(), Some("0x0000000000000000000000000000000000000001"));
+
+        let conditions = v.conditions.expect("conditions");
+        assert!(conditions.ftso_anchor_feeds && conditions.ftso_block_latency_feeds);
+        assert_eq!(conditions.passes_held, Some(3));
+
+        let rates = v.reward_rates.expect("reward rates");
+        assert!((rates.combined - 0.0018).abs() < 1e-12);
+        assert!(rates.combined_avg_fallback);
+        assert_eq!(rates.apy, annualize(rates.combined, DEFAULT_EPOCHS_PER_YEAR));
+        assert_eq!(v.estimated_apy, Some(rates.apy));
+
+        let stats = v.provider_stats.expect("provider stats");
+        assert_eq!(stats.availability, Some(0.985));
+        assert_eq!((v.rewards_signed, v.uptime_signed), (Some(1), Some(1)));
+
+        let mut unrewarded = fixture_entities().remove(0);
+        unrewarded.rewards = None;
+        let v = process_entity(&unrewarded, DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE);
+        assert!(v.reward_rates.is_none() && v.estimated_apy.is_none());
+    }
+
+    #[test]
+    fn equal_rates_rank_by_id_and_nan_ranks_last() {
+        let criteria = EligibilityCriteria::default();
+        let template = build(&criteria).eligible_nodes.remove(0);
+        let with_rate = |id: u32, combined: f64| {
+            let mut v = template.clone();
+            v.id = id;
+            if let Some(rates) = v.reward_rates.as_mut() {
+                rates.combined = combined;
+            }
+            v
+        };
+        let validators = vec![with_rate(30, 0.002), with_rate(10, f64::NAN), with_rate(40, 0.003), with_rate(20, 0.002)];
+
+        for shuffled in [validators.clone(), validators.into_iter().rev().collect()] {
+            let (eligible, _) = split_validators(shuffled, &HashSet::new(), &criteria);
+            assert_eq!(eligible.iter().map(|v| v.id).collect::<Vec<_>>(), vec![40, 20, 30, 10]);
+        }
+    }
+
+    #[test]
+    fn sse_event_frames_the_refresh_summary() {
+        let data = build(&EligibilityCriteria::default());
+        let frame = sse_event(&RefreshEvent::from(&data)).unwrap();
+        let frame = std::str::from_utf8(&frame).unwrap();
+
+        let json = frame.strip_prefix("event: refresh\ndata: ").and_then(|f| f.strip_suffix("\n\n")).unwrap();
+        assert!(!json.contains('\n'));
+        let event: RefreshEvent = serde_json::from_str(json).unwrap();
+        assert_eq!(event.timestamp, data.timestamp);
+        assert_eq!((event.eligible_count, event.ineligible_count), (data.eligible_count, data.ineligible_count));
+    }
+
+    #[test]
+    fn field_coverage_counts_missing_objects() {
+        let coverage = field_coverage(&fixture_entities());
+        assert_eq!(coverage.entities, 9);
+        assert_eq!(coverage.missing_conditions, 1);
+        assert_eq!((coverage.missing_rewards, coverage.missing_provider_stats), (0, 0));
+    }
+
+    #[test]
+    fn normalized_combined_scales_each_component_over_the_eligible_set() {
+        let template = build(&EligibilityCriteria::default()).eligible_nodes.remove(0);
+        let with_rates = |id: u32, wnat: f64, mirror: f64, pure: f64| {
+            let mut v = template.clone();
+            v.id = id;
+            if let Some(rates) = v.reward_rates.as_mut() {
+                (rates.wnat, rates.mirror, rates.pure) = (wnat, mirror, pure);
+            }
+            v
+        };
+        // mirror is the same for everyone, so it contributes nothing
+        let mut validators = vec![
+            with_rates(1, 0.001, 0.5, 0.0),
+            with_rates(2, 0.003, 0.5, 0.02),
+            with_rates(3, 0.002, 0.5, 0.01),
+        ];
+        normalize_reward_rates(&mut validators);
+
+        let normalized: Vec<f64> = validators.iter()
+            .map(|v| v.reward_rates.as_ref().and_then(|r| r.normalized_combined).unwrap())
+            .collect();
+        assert_eq!(normalized[0], 0.0);
+        assert!((normalized[1] - 2.0).abs() < 1e-9);
+        assert!((normalized[2] - 1.0).abs() < 1e-9);
+        assert!(validators.iter().all(|v| v.reward_rates.as_ref().is_some_and(|r| r.mirror == 0.5)));
+    }
+
+    #[test]
+    fn log_filter_layers_directives_over_the_level() {
+        assert_eq!(log_filter(None, None), ("info".to_string(), Vec::new()));
+        assert_eq!(log_filter(Some("warn"), None).0, "warn");
+        assert_eq!(
+            log_filter(Some("warn"), Some("flare_validator_api=debug")).0,
+            "warn,flare_validator_api=debug"
+        );
+
+        // A bad half doesn't take the good one down with it
+        let (spec, warnings) = log_filter(Some("loud"), Some("flare_validator_api=debug"));
+        assert_eq!(spec, "info,flare_validator_api=debug");
+        assert_eq!(warnings.len(), 1);
+        let (spec, warnings) = log_filter(Some("warn"), Some("flare_validator_api=["));
+        assert_eq!(spec, "warn");
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn selection_treats_unknown_activity_as_inactive() {
+        let mut v = build(&EligibilityCriteria::default()).eligible_nodes.remove(0);
+        assert!(provider_active(&v));
+
+        for active in [Some(false), None] {
+            if let Some(stats) = v.provider_stats.as_mut() {
+                stats.active = active;
+            }
+            assert!(!provider_active(&v));
+        }
+        v.provider_stats = None;
+        assert!(!provider_active(&v));
+    }
+
+    #[test]
+    fn uptime_filter_drops_unknown_counts() {
+        let mut validators: Vec<Validator> = fixture_entities().iter()
+            .take(3)
+            .map(|e| process_entity(e, DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE))
+            .collect();
+        validators[1].uptime_signed = Some(0);
+        validators[2].uptime_signed = None;
+
+        let query = |min: &str| HashMap::from([("min_uptime_signed".to_string(), min.to_string())]);
+        let ids = |min: &str| -> Vec<u32> {
+            ValidatorFilter::from_query(&query(min)).unwrap().apply(&validators).iter().map(|v| v.id).collect()
+        };
+        assert_eq!(ids("1"), vec![validators[0].id]);
+        assert_eq!(ids("0"), vec![validators[0].id, validators[1].id]);
+        assert!(ValidatorFilter::from_query(&query("-1")).is_err());
+    }
+
+    #[test]
+    fn availability_is_scaled_and_clamped() {
+        assert_eq!(scale_availability(98.5, 100.0, 1), 0.985);
+        assert_eq!(scale_availability(9850.0, 10_000.0, 1), 0.985);
+        assert_eq!(scale_availability(0.985, 1.0, 1), 0.985);
+        assert_eq!(scale_availability(250.0, 100.0, 1), 1.0);
+        assert_eq!(scale_availability(-5.0, 100.0, 1), 0.0);
+    }
+
+    #[test]
+    fn fully_eligible_entity_is_eligible() {
+        let data = build(&EligibilityCriteria::default());
+        let v = find(&data, 1);
+
+        assert!(is_eligible(v, &EligibilityCriteria::default()));
+        assert!(data.eligible_nodes.iter().any(|e| e.id == 1));
+        assert_eq!(v.unmet_criteria, None);
+    }
+
+    #[test]
+    fn each_failing_condition_makes_entity_ineligible() {
+        let data = build(&EligibilityCriteria::default());
+
+        // ids 3-8 each fail exactly one condition
+        for id in 3..=8 {
+            let v = find(&data, id);
+            assert!(data.ineligible_nodes.iter().any(|i| i.id == id), "validator {} should be ineligible", id);
+            assert_eq!(v.unmet_criteria, Some(1), "validator {} should fail one criterion", id);
+        }
+    }
+
+    #[test]
+    fn missing_conditions_fail_every_criterion() {
+        let data = build(&EligibilityCriteria::default());
+        let v = find(&data, 9);
+
+        assert!(v.conditions.is_none());
+        assert_eq!(v.unmet_criteria, Some(ConditionFlag::ALL.len()));
+        assert_eq!(data.ineligible_nodes.last().map(|v| v.id), Some(9), "near-misses come first");
+
+        // Ignoring every flag still leaves the missing data itself unmet
+        let all: HashSet<ConditionFlag> = ConditionFlag::ALL.iter().copied().collect();
+        let resplit = resplit(Arc::new(data), &all, &EligibilityCriteria::default());
+        let v = find(&resplit, 9);
+        assert!(resplit.ineligible_nodes.iter().any(|i| i.id == 9));
+        assert_eq!(v.unmet_criteria, Some(1));
+    }
+
+    #[test]
+    fn eligible_sorted_by_combined_rate() {
+        let data = build(&EligibilityCriteria::default());
+        let ids: Vec<u32> = data.eligible_nodes.iter().map(|v| v.id).collect();
+
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(data.total_validators, 9);
+        assert_eq!(data.eligible_count, 2);
+        assert_eq!(data.ineligible_count, 7);
+        assert!(data.eligible_nodes[0].rate_vs_median > data.eligible_nodes[1].rate_vs_median);
+    }
+
+    #[test]
+    fn min_combined_rate_gates_eligibility() {
+        // Both pass every condition, only one clears a 0.2% gate
+        let validator = |id: u32, rate: f64| {
+            let entity = entity(serde_json::json!({
+                "id": id,
+                "entityminimalconditions": {
+                    "ftso_scaling": true,
+                    "ftso_fast_updates": true,
+                    "fdc": true,
+                    "staking": true,
+                    "passes_held": 3,
+                    "eligible_for_reward": true,
+                },
+                "rewards": { "reward_rate_wnat": rate },
+            }));
+            process_entity(&entity, DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE)
+        };
+        let validators = vec![validator(1, 0.0018), validator(2, 0.0025)];
+
+        let (eligible, _) = split_validators(validators.clone(), &HashSet::new(), &EligibilityCriteria::default());
+        assert_eq!(eligible.len(), 2);
+
+        let criteria = EligibilityCriteria { min_combined_rate: Some(0.002), ..EligibilityCriteria::default() };
+        let (eligible, ineligible) = split_validators(validators, &HashSet::new(), &criteria);
+        let ids = |list: &[Validator]| list.iter().map(|v| v.id).collect::<Vec<_>>();
+        assert_eq!(ids(&eligible), vec![2]);
+        assert_eq!(ids(&ineligible), vec![1]);
+    }
+
+    #[test]
+    fn annualize_compounds_per_epoch() {
+        assert_eq!(annualize(0.0, DEFAULT_EPOCHS_PER_YEAR), 0.0);
+        assert!((annualize(0.01, 1.0) - 0.01).abs() < 1e-12);
+        assert!((annualize(0.01, 2.0) - 0.0201).abs() < 1e-12);
+
+        let entity = entity(serde_json::json!({
+            "id": 1,
+            "rewards": { "reward_rate_wnat": 0.0006, "reward_rate_mirror": 0.0003, "reward_rate_pure": 0.0009 },
+        }));
+        let rates = process_entity(&entity, DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE).reward_rates.unwrap();
+        assert!((rates.combined - 0.0018).abs() < 1e-12);
+        let expected = (1.0 + rates.combined).powf(DEFAULT_EPOCHS_PER_YEAR) - 1.0;
+        assert!((rates.apy - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn reward_rates_state_their_units() {
+        let entity = fixture_entities().remove(0);
+        let rates = process_entity(&entity, 52.0, DEFAULT_AVAILABILITY_SCALE).reward_rates.unwrap();
+        assert_eq!(rates.units, RateUnits::new(52.0));
+        assert_eq!(rates.units.scale, "fraction");
+        assert_eq!(rates.units.period, "reward_epoch");
+        // Upstream sends fractions, which pass through unscaled
+        assert_eq!(Some(rates.wnat), entity.rewards.as_ref().unwrap().reward_rate_wnat);
+
+        let mut old = serde_json::to_value(&rates).unwrap();
+        old.as_object_mut().unwrap().remove("units");
+        let old: RewardRates = serde_json::from_value(old).unwrap();
+        assert_eq!(old.units, RateUnits::default());
+    }
+
+    #[test]
+    fn resplit_shares_cached_data_unless_ignoring() {
+        let criteria = EligibilityCriteria::default();
+        let data = Arc::new(build(&criteria));
+
+        let same = resplit(Arc::clone(&data), &HashSet::new(), &criteria);
+        assert!(Arc::ptr_eq(&same, &data));
+
+        let ignore = HashSet::from([ConditionFlag::Fdc]);
+        let relaxed = resplit(Arc::clone(&data), &ignore, &criteria);
+        assert!(!Arc::ptr_eq(&relaxed, &data));
+        assert!(relaxed.eligible_count > data.eligible_count);
+        assert_eq!(relaxed.total_validators, data.total_validators);
+    }
+
+    #[test]
+    fn page_slice_clamps_to_the_list() {
+        let items = [1, 2, 3, 4, 5];
+        assert_eq!(PageRequest { offset: 1, limit: 2 }.slice(&items), &[2, 3]);
+        assert_eq!(PageRequest { offset: 4, limit: 10 }.slice(&items), &[5]);
+        assert!(PageRequest { offset: 5, limit: 10 }.slice(&items).is_empty());
+        assert!(PageRequest { offset: 9, limit: usize::MAX }.slice(&items).is_empty());
+    }
+
+    #[test]
+    fn list_cap_only_applies_to_unpaged_requests() {
+        let mut unpaged = vec![1, 2, 3, 4, 5];
+        assert!(apply_list_cap(&mut unpaged, Some(2), false));
+        assert_eq!(unpaged, vec![1, 2]);
+        assert!(!apply_list_cap(&mut vec![1, 2], Some(2), false));
+
+        // Paging past the cap reaches the validators an unpaged request leaves out
+        let mut paged = vec![1, 2, 3, 4, 5];
+        assert!(!apply_list_cap(&mut paged, Some(2), true));
+        let page = PageRequest { offset: 2, limit: 2 };
+        assert!(page.over(paged.len()).unwrap().has_more);
+        assert_eq!(page.slice(&paged), &[3, 4]);
+    }
+
+    #[test]
+    fn fuzzy_distance_matches_closest_substring() {
+        assert_eq!(fuzzy_distance("flare", "Flare Oracle"), 0);
+        assert_eq!(fuzzy_distance("oracle", "Flare Oracle"), 0);
+        assert_eq!(fuzzy_distance("orakle", "Flare Oracle"), 1);
+        assert_eq!(fuzzy_distance("flre", "Flare Oracle"), 1);
+        assert_eq!(fuzzy_distance("xyz", "Flare Oracle"), 3);
+        assert_eq!(max_fuzzy_distance("fl"), 0);
+        assert_eq!(max_fuzzy_distance("orakle"), 2);
+    }
+
+    #[test]
+    fn fetch_errors_map_to_status_codes() {
+        use actix_web::http::StatusCode;
+        let ttl = Duration::from_secs(300);
+        let decode = || FetchError::Decode(serde_json::from_str::<u32>("x").unwrap_err());
+
+        let e = AppError::from_fetch(&decode(), ttl);
+        assert!(matches!(e, AppError::Deserialize(_)));
+        assert_eq!(e.status_code(), StatusCode::BAD_GATEWAY);
+
+        let partial = FetchError::PartialPage { offset: 200, fetched: 200, source: Box::new(decode()) };
+        assert!(matches!(AppError::from_fetch(&partial, ttl), AppError::Deserialize(_)));
+        let capped = FetchError::PageLimit { pages: MAX_UPSTREAM_PAGES, fetched: 10_000 };
+        assert!(!capped.is_retryable());
+        assert!(matches!(AppError::from_fetch(&capped, ttl), AppError::UpstreamBadResponse(_)));
+
+        let e = AppError::from_fetch(&FetchError::BodyTimeout(Duration::from_secs(8)), ttl);
+        let response = e.error_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("retry-after").unwrap(), "300");
+
+        assert_eq!(AppError::NotFound("gone".to_string()).status_code(), StatusCode::NOT_FOUND);
+        let late = AppError::DeadlineExceeded("slow".to_string());
+        assert_eq!(late.status_code(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(late.kind(), "deadline_exceeded");
+    }
+
+    #[test]
+    fn content_hash_ignores_fetch_timestamp() {
+        let data = build(&EligibilityCriteria::default());
+        let mut refetched = data.clone();
+        refetched.timestamp = "2030-01-01T00:00:00Z".to_string();
+        assert_eq!(content_hash(&data), content_hash(&refetched));
+
+        refetched.eligible_nodes[0].name.push('!');
+        assert_ne!(content_hash(&data), content_hash(&refetched));
+    }
+
+    #[test]
+    fn sse_subscribers_reject_or_evict_at_the_cap() {
+        let rejecting = Arc::new(SseSubscribers::new(Some(2), SseFullPolicy::Reject));
+        let first = rejecting.admit().expect("room for the first");
+        let _second = rejecting.admit().expect("room for the second");
+        assert!(rejecting.admit().is_none());
+        drop(first);
+        assert_eq!(rejecting.count(), 1);
+        assert!(rejecting.admit().is_some());
+
+        let evicting = Arc::new(SseSubscribers::new(Some(1), SseFullPolicy::EvictOldest));
+        let oldest = evicting.admit().unwrap();
+        let newest = evicting.admit().expect("the oldest makes room");
+        assert_eq!(evicting.count(), 1);
+        assert_eq!(evicting.evicted.load(Ordering::Relaxed), 1);
+        // The evicted stream still sees the notification, and leaving doesn't drop the newer one
+        assert!(futures::FutureExt::now_or_never(oldest.evicted.notified()).is_some());
+        drop(oldest);
+        assert_eq!(evicting.open.lock().front().map(|(id, _)| *id), Some(newest.id));
+
+        assert_eq!("Evict_Oldest".parse::<SseFullPolicy>(), Ok(SseFullPolicy::EvictOldest));
+        assert!("drop".parse::<SseFullPolicy>().is_err());
+    }
+
+    #[test]
+    fn rate_limiter_refills_over_time() {
+        let limiter = RateLimiter::new(60);
+        let ip: std::net::IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+
+        for _ in 0..60 {
+            assert!(limiter.check(ip, start).is_ok());
+        }
+        let wait = limiter.check(ip, start).unwrap_err();
+        assert!((wait.as_secs_f64() - 1.0).abs() < 1e-6);
+
+        // Other clients have their own bucket
+        assert!(limiter.check("192.0.2.2".parse().unwrap(), start).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(ip, start + Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn forwarded_headers_only_count_from_trusted_proxies() {
+        use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+
+        assert!(parse_trusted_proxies("10.0.0.0/33").is_err());
+        assert!(parse_trusted_proxies("proxy.local").is_err());
+        let proxies = TrustedProxies {
+            networks: parse_trusted_proxies("10.0.0.0/8, 2001:db8::/32,192.0.2.7").unwrap(),
+            any_peer: false,
+        };
+        assert!(proxies.trusts("10.1.2.3".parse().unwrap()));
+        assert!(proxies.trusts("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(proxies.trusts("2001:db8::1".parse().unwrap()));
+        assert!(!proxies.trusts("192.0.2.8".parse().unwrap()));
+
+        let headers = |xff: &str, proto: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(HeaderName::from_static("x-forwarded-for"), HeaderValue::from_str(xff).unwrap());
+            headers.insert(HeaderName::from_static("x-forwarded-proto"), HeaderValue::from_str(proto).unwrap());
+            headers
+        };
+        let ip = |s: &str| Some(s.parse::<std::net::IpAddr>().unwrap());
+
+        // A stranger's headers are ignored outright
+        let spoofed = headers("203.0.113.1", "https");
+        assert_eq!(
+            proxies.resolve(ip("198.51.100.9"), &spoofed),
+            ClientAddr { ip: ip("198.51.100.9"), forwarded_proto: None },
+        );
+
+        // Behind two of our proxies the client is the first untrusted hop,
+        // whatever it prepended itself
+        let chained = headers("1.1.1.1, 203.0.113.5, 10.0.0.2", "HTTPS");
+        assert_eq!(
+            proxies.resolve(ip("10.0.0.1"), &chained),
+            ClientAddr { ip: ip("203.0.113.5"), forwarded_proto: Some("https".to_string()) },
+        );
+        assert_eq!(proxies.resolve(ip("10.0.0.1"), &headers("garbage, 10.0.0.2", "http")).ip, ip("10.0.0.2"));
+
+        // The deprecated flag trusts any peer, but only for one hop
+        let legacy = TrustedProxies { networks: Vec::new(), any_peer: true };
+        assert_eq!(legacy.resolve(ip("198.51.100.9"), &chained).ip, ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn ineligibility_reasons_name_each_failed_condition() {
+        let criteria = EligibilityCriteria::default();
+        let data = build(&criteria);
+        let reasons = |id| ineligibility_reasons(find(&data, id), &HashSet::new(), &criteria);
+
+        assert!(reasons(1).is_empty());
+        assert_eq!(reasons(9), vec!["no conditions data available"]);
+        for v in &data.ineligible_nodes {
+            let reasons = ineligibility_reasons(v, &HashSet::new(), &criteria);
+            if v.conditions.is_some() {
+                assert_eq!(Some(reasons.len()), v.unmet_criteria, "validator {}", v.id);
+            }
+        }
+
+        let strict = EligibilityCriteria { min_passes: 4, min_combined_rate: Some(0.002), ..criteria };
+        let reasons = ineligibility_reasons(find(&data, 1), &HashSet::new(), &strict);
+        assert_eq!(reasons, vec![
+            "only 3 passes held, 4 required".to_string(),
+            "combined rate 0.0018 below the minimum 0.002".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn testnet_shaped_entities_deserialize() {
+        // No providersuccessrate, no node_ids and the newer condition names
+        let page = r#"{"results": [{
+            "id": 7,
+            "display_name": "Coston Provider",
+            "denormalizedentity": {"delegation_address": "0x07"},
+            "entityminimalconditions": {
+                "ftso_anchor_feeds": true, "ftso_block_latency_feeds": false,
+                "fdc": true, "staking": true, "passes_held": 3, "eligible_for_reward": true
+            },
+            "rewards": {"reward_rate_wnat": 0.001}
+        }]}"#;
+        let decode = |network| {
+            let mut raw: serde_json::Value = serde_json::from_str(page).unwrap();
+            raw["results"].as_array_mut().unwrap().iter_mut().for_each(|e| apply_condition_aliases(e, network));
+            let entities = serde_json::from_value::<FlareEntityList>(raw).expect("testnet page").results;
+            process_entity(&entities[0], DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE)
+        };
+
+        let v = decode(Network::Coston);
+        assert!(v.node_ids.is_empty() && v.provider_stats.is_none());
+        let conditions = v.conditions.expect("conditions");
+        assert!(conditions.ftso_anchor_feeds && !conditions.ftso_block_latency_feeds);
+        // Mainnet doesn't know the testnet names, so they read as missing
+        assert!(!decode(Network::Flare).conditions.expect("conditions").ftso_anchor_feeds);
+
+        let expected = expected_entity_fields(Network::Coston2);
+        assert!(expected.contains("entityminimalconditions.ftso_anchor_feeds"));
+        assert!(!expected.contains("entityminimalconditions.ftso_scaling"));
+        assert!(expected_entity_fields(Network::Flare).contains("entityminimalconditions.ftso_scaling"));
+
+        assert_eq!("Coston2".parse::<Network>(), Ok(Network::Coston2));
+        assert!("songbird".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn delegation_tx_lists_bips_and_wei() {
+        let mut eligible = build(&EligibilityCriteria::default()).eligible_nodes;
+        let picks: Vec<&Validator> = eligible.iter().take(2).collect();
+
+        // 12.34 and 0.66 at precision 2
+        let tx = delegation_tx(7, &picks, &[3.0, 1.0], Some(&[1234, 66]), 2).unwrap();
+        assert_eq!(tx.method, "batchDelegate");
+        assert_eq!(tx.ids, vec![picks[0].id, picks[1].id]);
+        assert_eq!(tx.delegatees[0], picks[0].delegation_address.clone().unwrap());
+        assert_eq!(tx.bips, vec![7500, 2500]);
+        assert_eq!(tx.amounts_wei.unwrap(), vec!["12340000000000000000", "660000000000000000"]);
+        assert!(delegation_tx(7, &picks, &[1.0, 1.0], None, 2).unwrap().amounts_wei.is_none());
+
+        eligible[1].delegation_address = None;
+        let picks: Vec<&Validator> = eligible.iter().take(2).collect();
+        let err = delegation_tx(7, &picks, &[1.0, 1.0], None, 2).unwrap_err();
+        assert!(err.contains(&picks[1].id.to_string()));
+    }
+
+    #[test]
+    fn selection_drift_pairs_over_with_under() {
+        let data = build(&EligibilityCriteria::default());
+        let (a, b) = (data.eligible_nodes[0].id, data.eligible_nodes[1].id);
+        let gone = data.ineligible_nodes[0].id;
+
+        // Holding 6 in a, 4 in an ineligible validator and 0 in an unknown one;
+        // the recommendation is 5 and 5 in a and b
+        let held = [(a, 600), (gone, 400), (9999, 0)];
+        let drift = selection_drift(&data, &held, &[(a, 500), (b, 500)], 100.0);
+
+        assert_eq!(drift.total_amount, 10.0);
+        assert_eq!((drift.drift_amount, drift.drift_share), (5.0, 0.5));
+        assert_eq!(drift.now_ineligible, vec![gone]);
+        assert_eq!(drift.unknown_ids, vec![9999]);
+        let positions: Vec<(u32, &str, f64)> = drift.positions.iter().map(|p| (p.id, p.status.as_str(), p.difference)).collect();
+        assert_eq!(positions, vec![(a, "eligible", -1.0), (gone, "ineligible", -4.0), (9999, "unknown", 0.0), (b, "eligible", 5.0)]);
+        let moves: Vec<(u32, u32, f64)> = drift.moves.iter().map(|m| (m.from, m.to, m.amount)).collect();
+        assert_eq!(moves, vec![(gone, b, 4.0), (a, b, 1.0)]);
+
+        let on_target = selection_drift(&data, &[(a, 500), (b, 500)], &[(b, 500), (a, 500)], 100.0);
+        assert_eq!(on_target.drift_share, 0.0);
+        assert!(on_target.moves.is_empty());
+    }
+
+    #[test]
+    fn allocate_units_sums_exactly() {
+        assert_eq!(allocate_units(10_000, &[1.0, 1.0, 1.0]), vec![3334, 3333, 3333]);
+        assert_eq!(allocate_units(100, &[1.0, 3.0]), vec![25, 75]);
+        // The rounding remainder goes to the highest weight, not the first pick
+        assert_eq!(allocate_units(10, &[1.0, 2.0, 2.5]), vec![1, 3, 6]);
+        assert_eq!(allocate_units(7, &[0.0, 0.0]), vec![4, 3]);
+        assert!(allocate_units(5, &[]).is_empty());
+    }
+
+    #[test]
+    fn dedup_merges_shared_addresses_and_nodes_conservatively() {
+        let mut entities = fixture_entities();
+        // 3 shares 1's delegation address, 4 shares a node with 3
+        entities[2].denormalizedsigningpolicy = Some(FlareSigningPolicy {
+            delegation_address: Some("0x0000000000000000000000000000000000000001".to_uppercase()),
+        });
+        let node = entities[2].denormalizedentity.as_ref().unwrap().node_ids[0].clone();
+        entities[3].denormalizedentity.as_mut().unwrap().node_ids.push(node);
+        for (i, rewards, uptime) in [(0, Some(7), Some(9)), (2, Some(5), Some(6)), (3, Some(8), None)] {
+            let denormalized = entities[i].denormalizedentity.as_mut().unwrap();
+            (denormalized.rewards_signed, denormalized.uptime_signed) = (rewards, uptime);
+        }
+
+        let validators: Vec<Validator> = entities.iter()
+            .map(|e| process_entity(e, DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE))
+            .collect();
+        let merged = dedup_validators(validators.clone(), DEFAULT_EPOCHS_PER_YEAR);
+        assert_eq!(merged.len(), 7);
+
+        let v = &merged[0];
+        assert_eq!((v.id, v.merged_ids.as_slice()), (1, &[3, 4][..]));
+        assert_eq!(v.node_ids.len(), validators[0].node_ids.len() + validators[2].node_ids.len() + validators[3].node_ids.len() - 1);
+        // 3 lacks anchor feeds and 4 block-latency feeds, so the merge has neither
+        let conditions = v.conditions.as_ref().unwrap();
+        assert!(!conditions.ftso_anchor_feeds && !conditions.ftso_block_latency_feeds && conditions.fdc);
+        let combined: f64 = [0, 2, 3].iter().map(|&i| combined_rate(&validators[i])).sum::<f64>() / 3.0;
+        assert!((combined_rate(v) - combined).abs() < 1e-12);
+        // The lowest count in the group, skipping members that report none
+        assert_eq!((v.rewards_signed, v.uptime_signed), (Some(5), Some(6)));
+
+        assert!(merged[1..].iter().all(|v| v.merged_ids.is_empty()));
+
+        // Without shared keys nothing changes
+        let untouched: Vec<Validator> = fixture_entities().iter()
+            .map(|e| process_entity(e, DEFAULT_EPOCHS_PER_YEAR, DEFAULT_AVAILABILITY_SCALE))
+            .collect();
+        assert_eq!(dedup_validators(untouched, DEFAULT_EPOCHS_PER_YEAR).len(), 9);
+    }
+
+    #[test]
+    fn openapi_required_fields_match_serialized_output() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = &spec["components"]["schemas"];
+        let keys = |value: serde_json::Value| -> Vec<String> {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let required = |name: &str| -> Vec<String> {
+            let mut required: Vec<String> = schemas[name]["required"].as_array().unwrap().iter()
+                .map(|k| k.as_str().unwrap().to_string())
+                .collect();
+            required.sort();
+            required
+        };
+
+        // The entity without conditions leaves every optional field unset, so
+        // what it serializes is exactly what is always present
+        let data = build(&EligibilityCriteria::default());
+        let bare = data.ineligible_nodes.iter().find(|v| v.conditions.is_none()).unwrap();
+        assert!(bare.unmet_criteria.is_some());
+        let bare = Validator { unmet_criteria: None, ..bare.clone() };
+        assert_eq!(keys(serde_json::to_value(&bare).unwrap()), required("Validator"));
+        assert_eq!(schemas["Validator"]["properties"]["node_id"]["type"], serde_json::json!(["string", "null"]));
+
+        let list = ValidatorsListResponse {
+            timestamp: data.timestamp.clone(),
+            count: 0,
+            validators: Vec::new(),
+            truncated: false,
+            total_available: None,
+        };
+        assert_eq!(keys(serde_json::to_value(&list).unwrap()), required("ValidatorsListResponse"));
+        assert!(schemas["ValidatorsListResponse"]["properties"]["total_available"].is_object());
+
+        let meta = EnvelopeMeta {
+            timestamp: data.timestamp.clone(),
+            data_timestamp: None,
+            cache_age_secs: None,
+            cache_status: None,
+            pagination: None,
+        };
+        assert_eq!(keys(serde_json::to_value(&meta).unwrap()), required("EnvelopeMeta"));
+        let envelope = ApiEnvelope { data: Some(list), meta, errors: Vec::new() };
+        assert_eq!(keys(serde_json::to_value(&envelope).unwrap()), required("ApiEnvelope_ValidatorsListResponse"));
+    }
+
+    #[test]
+    fn list_query_params_are_documented() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let params = |path: &str| -> Vec<String> {
+            spec["paths"][path]["get"]["parameters"].as_array().unwrap().iter()
+                .map(|p| p["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+        for list in ["", "/eligible", "/ineligible", "/top"] {
+            assert_eq!(
+                params(&format!("/api/validators{}", list)),
+                params(&format!("/api/v2/validators{}", list)),
+            );
+        }
+        assert!(params("/api/validators/ineligible").contains(&"with_reasons".to_string()));
+
+        // Every documented name is read by the shared parsers: a bad value
+        // for it is rejected before any data is fetched
+        let req = actix_web::test::TestRequest::default().to_http_request();
+        let rejects = |query: HashMap<String, String>| {
+            ResponseFormat::negotiate(&req, &query).is_err()
+                || parse_ignore(&query).is_err()
+                || ValidatorFilter::from_query(&query).is_err()
+                || ListSort::from_query(&query).is_err()
+                || PageRequest::from_query(&query).is_err()
+                || FieldMask::from_query(&query).is_err()
+        };
+        let documented = params("/api/validators");
+        assert!(!documented.is_empty());
+        for name in documented {
+            let mut query = HashMap::from([(name.clone(), "bogus".to_string())]);
+            match name.as_str() {
+                "fields" => { query.insert("strict".to_string(), "true".to_string()); }
+                "strict" => { query.insert("fields".to_string(), "name".to_string()); }
+                _ => {}
+            }
+            assert!(rejects(query), "'{}' is documented but not parsed", name);
+        }
+    }
+
+    #[test]
+    fn v2_lists_carry_metadata_in_the_envelope() {
+        let body_json = |response: HttpResponse| -> serde_json::Value {
+            let bytes = futures::executor::block_on(to_bytes(response.into_body())).unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        };
+        let page = || Ok(ListOutcome::Page(ListPage {
+            body: ValidatorsListResponse {
+                timestamp: "2026-01-01T00:00:00Z".to_string(),
+                count: 0,
+                validators: Vec::new(),
+                truncated: false,
+                total_available: None,
+            },
+            fields: None,
+            format: ResponseFormat::Json,
+            pagination: Pagination { total: 0, offset: 0, limit: 100, has_more: false },
+            data_timestamp: "2026-01-01T00:00:00Z".to_string(),
+            cache_status: CacheStatus::Stale,
+            etag: "W/\"0\"".to_string(),
+        }));
+
+        let flat = body_json(render_list(ListShape::Flat, Some(42), page()));
+        assert_eq!(flat["pagination"]["limit"], 100);
+        assert!(flat.get("served_timestamp").is_some());
+
+        let wrapped = body_json(render_list(ListShape::Enveloped, Some(42), page()));
+        assert_eq!(wrapped["data"]["count"], 0);
+        assert!(wrapped["data"].get("pagination").is_none());
+        assert!(wrapped["data"].get("served_timestamp").is_none());
+        assert_eq!(wrapped["meta"]["pagination"]["limit"], 100);
+        assert_eq!(wrapped["meta"]["cache_status"], "stale");
+        assert_eq!(wrapped["meta"]["cache_age_secs"], 42);
+        assert_eq!(wrapped["meta"]["data_timestamp"], "2026-01-01T00:00:00Z");
+        assert_eq!(wrapped["errors"], serde_json::json!([]));
+
+        let rejected: Result<ListOutcome<ValidatorsListResponse>, ListError> =
+            Err(ListError::BadRequest("'limit' must be a positive integer".to_string()));
+        let response = render_list(ListShape::Enveloped, None, rejected);
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers().get(actix_web::http::header::VARY).unwrap(), "Accept");
+        let rejected = body_json(response);
+        assert!(rejected["data"].is_null());
+        assert_eq!(rejected["errors"], serde_json::json!(["'limit' must be a positive integer"]));
+    }
+
+    #[test]
+    fn page_size_is_clamped_to_the_upstream_range() {
+        assert_eq!(clamp_page_size(DEFAULT_UPSTREAM_PAGE_SIZE), DEFAULT_UPSTREAM_PAGE_SIZE);
+        assert_eq!(clamp_page_size(0), 1);
+        assert_eq!(clamp_page_size(MAX_UPSTREAM_PAGE_SIZE + 1), MAX_UPSTREAM_PAGE_SIZE);
+    }
+
+    #[test]
+    fn random_pick_is_reproducible_and_honors_weights() {
+        let pick = |weights: &[f64], weighted: bool, seed: u64| {
+            pick_one(weights, weighted, &mut ChaCha8Rng::seed_from_u64(seed))
+        };
+
+        assert_eq!(pick(&[], false, 1), None);
+        let weights = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(pick(&weights, false, 7), pick(&weights, false, 7));
+        assert_eq!(pick(&weights, false, 7).unwrap().1, 0.25);
+        // Only one candidate carries weight, so a weighted draw always lands on it
+        for seed in 0..20 {
+            assert_eq!(pick(&[0.0, 0.0, 5.0], true, seed), Some((2, 1.0)));
+        }
+        let (_, probability) = pick(&[0.0, 0.0], true, 3).unwrap();
+        assert_eq!(probability, 0.5);
+    }
+
+    #[test]
+    fn batch_lookup_resolves_each_validator_once() {
+        let data = build(&EligibilityCriteria::default());
+        let top = &data.eligible_nodes[0];
+        let other = data.ineligible_nodes.iter().find(|v| !v.node_ids.is_empty()).unwrap();
+        let node_ids = vec![
+            top.node_ids[0].clone(),
+            "NodeID-Missing".to_string(),
+            other.node_ids[0].clone(),
+            "NodeID-Missing".to_string(),
+        ];
+
+        let found = batch_lookup(&data, &[top.id, 999_999, top.id, 999_999], &node_ids);
+        assert_eq!(found.validators[0].validator.id, top.id);
+        assert_eq!(found.validators[0].rank, Some(1));
+        assert_eq!(found.not_found_ids, vec![999_999]);
+        assert_eq!(found.not_found_node_ids, vec!["NodeID-Missing".to_string()]);
+        assert_eq!(found.count, 2);
+        assert_eq!(found.validators[1].validator.id, other.id);
+        assert!(!found.validators[1].eligible);
+        assert_eq!(found.validators[1].rank, None);
+    }
+
+    #[test]
+    fn field_mask_keeps_only_the_listed_paths() {
+        let query = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        let fields = "id,reward_rates.combined,conditions,conditions.fdc,bogus";
+        let mask = FieldMask::from_query(&query(&[("fields", fields)])).unwrap().unwrap();
+        assert!(FieldMask::from_query(&query(&[("fields", fields), ("strict", "true")])).is_err());
+        assert!(FieldMask::from_query(&query(&[])).unwrap().is_none());
+        assert!(validator_field_paths().contains("provider_stats.availability"));
+
+        let data = build(&EligibilityCriteria::default());
+        let mut list = serde_json::to_value(&data.eligible_nodes).unwrap();
+        mask.apply(&mut list);
+        let first = list[0].as_object().unwrap();
+        let mut keys: Vec<&String> = first.keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["conditions", "id", "reward_rates"]);
+        assert_eq!(first["reward_rates"].as_object().unwrap().len(), 1);
+        assert_eq!(first["reward_rates"]["combined"], data.eligible_nodes[0].reward_rates.as_ref().unwrap().combined);
+        // Listing the parent keeps all of it
+        assert_eq!(first["conditions"], serde_json::to_value(&data.eligible_nodes[0].conditions).unwrap());
+    }
+
+    #[test]
+    fn selection_filters_apply_before_weighting() {
+        let mut eligible = build(&EligibilityCriteria::default()).eligible_nodes;
+        for (v, availability) in eligible.iter_mut().zip([0.995, 0.9]) {
+            v.provider_stats.as_mut().unwrap().availability = Some(availability);
+            v.provider_stats.as_mut().unwrap().active = Some(true);
+        }
+        let filter = |min: &str| {
+            ValidatorFilter::from_query(&HashMap::from([("min_availability".to_string(), min.to_string())])).unwrap()
+        };
+        let everyone = AddressFilter::default();
+
+        let (candidates, inactive) = selection_pool(&eligible, &[], &filter("0.99"), &everyone, false).unwrap();
+        assert_eq!(candidates.iter().map(|v| v.id).collect::<Vec<_>>(), vec![eligible[0].id]);
+        assert!(inactive.is_empty());
+        assert!(selection_pool(&eligible, &[], &filter("0.999"), &everyone, false).unwrap_err().contains("filters"));
+
+        let address = eligible[0].delegation_address.clone().unwrap();
+        let excluded = AddressFilter::from_query(&HashMap::from([("exclude".to_string(), address)])).unwrap();
+        assert!(selection_pool(&eligible, &[], &filter("0.99"), &excluded, false).unwrap_err().contains("'exclude'"));
+        assert_eq!(selection_pool(&[], &[], &filter("0.99"), &everyone, false).unwrap().0.len(), 0);
+    }
+
+    #[test]
+    fn rate_outliers_flag_values_beyond_the_iqr_fence() {
+        let template = build(&EligibilityCriteria::default()).eligible_nodes.remove(0);
+        let validators: Vec<Validator> = [0.0010, 0.0011, 0.0012, 0.0013, 0.0014, 0.5, f64::NAN]
+            .iter()
+            .enumerate()
+            .map(|(i, &combined)| {
+                let mut v = template.clone();
+                v.id = i as u32 + 1;
+                v.reward_rates.as_mut().unwrap().combined = combined;
+                v
+            })
+            .collect();
+
+        assert_eq!(rate_outliers(&validators), vec![6, 7]);
+        assert!(rate_outliers(&validators[..3]).is_empty());
+
+        let data = ValidatorResponse {
+            timestamp: "2026-01-01T00:00:00Z".to_string(),
+            total_validators: validators.len(),
+            eligible_count: validators.len(),
+            ineligible_count: 0,
+            eligible_nodes: validators,
+            ineligible_nodes: Vec::new(),
+            reward_epoch: None,
+        };
+        let stats = compute_stats(&data, &[6, 7]);
+        assert_eq!(stats.combined_rate.count, 5);
+        assert_eq!(stats.combined_rate.max, Some(0.0014));
+        assert_eq!(stats.eligible_count, 7);
+        assert_eq!(stats.excluded_outliers, vec![6, 7]);
+
+        // Without warm-up each view is computed on first use, then kept
+        let cold = CachedData::new(data.clone(), SystemTime::now(), false);
+        assert!(cold.stats.get().is_none() && cold.stats_without_outliers.get().is_none());
+        assert_eq!(cold.stats(true).excluded_outliers, vec![6, 7]);
+        assert!(cold.stats.get().is_none() && cold.stats_without_outliers.get().is_some());
+        let warm = CachedData::new(data, SystemTime::now(), true);
+        assert!(warm.stats.get().is_some() && warm.stats_without_outliers.get().is_some());
+    }
+
+    #[test]
+    fn reward_epoch_is_the_newest_one_upstream_reports() {
+        assert_eq!(build(&EligibilityCriteria::default()).reward_epoch, Some(312));
+
+        // Conditions stand in when rewards are missing, and a newer epoch wins
+        let mut entities = fixture_entities();
+        entities[0].rewards = None;
+        entities[0].entityminimalconditions.as_mut().unwrap().reward_epoch = Some(313);
+        assert_eq!(current_reward_epoch(&entities), Some(313));
+        assert_eq!(current_reward_epoch(&[]), None);
+
+        // Caches persisted before the field existed still load
+        let mut old = serde_json::to_value(build(&EligibilityCriteria::default())).unwrap();
+        old.as_object_mut().unwrap().remove("reward_epoch");
+        let old: ValidatorResponse = serde_json::from_value(old).unwrap();
+        assert_eq!(old.reward_epoch, None);
+    }
+
+    #[test]
+    fn response_format_follows_the_query_then_accept() {
+        use actix_web::test::TestRequest;
+
+        let negotiate = |accept: Option<&str>, format: Option<&str>| {
+            let mut req = TestRequest::default();
+            if let Some(accept) = accept {
+                req = req.insert_header((actix_web::http::header::ACCEPT, accept));
+            }
+            let query: HashMap<String, String> = format.map(|f| ("format".to_string(), f.to_string())).into_iter().collect();
+            ResponseFormat::negotiate(&req.to_http_request(), &query)
+        };
+
+        assert_eq!(negotiate(None, None), Ok(ResponseFormat::Json));
+        assert_eq!(negotiate(Some("text/csv"), None), Ok(ResponseFormat::Csv));
+        assert_eq!(negotiate(Some("text/csv"), Some("yaml")), Ok(ResponseFormat::Yaml));
+        assert_eq!(negotiate(Some("text/html, */*;q=0.8"), None), Ok(ResponseFormat::Json));
+        assert_eq!(negotiate(Some("application/json;q=0.5, application/msgpack"), None), Ok(ResponseFormat::MessagePack));
+        assert_eq!(negotiate(Some("application/yaml;q=0, text/csv;q=0.1"), None), Ok(ResponseFormat::Csv));
+        assert!(negotiate(Some("application/xml"), None).is_err());
+        assert!(negotiate(None, Some("xml")).is_err());
+
+        let data = build(&EligibilityCriteria::default());
+        let v = &data.eligible_nodes[0];
+        let body = |format| {
+            let response = validator_detail(format, CacheStatus::Hit, &data.timestamp, v);
+            let content_type = response.headers().get(actix_web::http::header::CONTENT_TYPE).cloned();
+            let bytes = futures::executor::block_on(to_bytes(response.into_body())).unwrap();
+            (content_type.unwrap(), bytes)
+        };
+
+        let (content_type, yaml) = body(ResponseFormat::Yaml);
+        assert_eq!(content_type, "application/yaml");
+        let yaml: serde_json::Value = serde_yaml::from_slice(&yaml).unwrap();
+        assert_eq!(yaml["id"], v.id);
+
+        let (content_type, msgpack) = body(ResponseFormat::MessagePack);
+        assert_eq!(content_type, "application/msgpack");
+        let msgpack: serde_json::Value = rmp_serde::from_slice(&msgpack).unwrap();
+        assert_eq!(msgpack["name"], v.name.as_str());
+
+        let (_, csv) = body(ResponseFormat::Csv);
+        let csv = String::from_utf8(csv.to_vec()).unwrap();
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.lines().nth(1).unwrap().starts_with(&format!("{},", v.id)));
+
+        let response = formatted(HttpResponse::Ok(), ResponseFormat::Csv, &data, || None);
+        assert_eq!(response.status(), actix_web::http::StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[test]
+    fn startup_check_wants_entities_with_conditions_or_rewards() {
+        assert_eq!(check_startup_fetch(&field_coverage(&fixture_entities())), Ok(()));
+        assert!(check_startup_fetch(&FieldCoverage::default()).is_err());
+
+        // A schema that only still matches on the id
+        let entities: Vec<FlareEntity> = serde_json::from_str(r#"[{"id": 1}, {"id": 2, "rewards": null}]"#).unwrap();
+        let e = check_startup_fetch(&field_coverage(&entities)).unwrap_err();
+        assert!(e.contains("incompatible"), "{}", e);
+
+        // Testnets without rewards still pass on their conditions
+        let entities: Vec<FlareEntity> = serde_json::from_str(r#"[{"id": 1, "entityminimalconditions": {}}]"#).unwrap();
+        assert_eq!(check_startup_fetch(&field_coverage(&entities)), Ok(()));
+    }
+
+    #[test]
+    fn score_weights_normalize_and_scale_each_term_to_the_pool() {
+        let query = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+
+        assert_eq!(ScoreWeights::from_query(&query(&[])), Ok(None));
+        assert_eq!(
+            ScoreWeights::from_query(&query(&[("w_reward", "2"), ("w_uptime", "2")])),
+            Ok(Some(ScoreWeights { reward: 0.5, availability: 0.0, uptime: 0.5 }))
+        );
+        assert!(ScoreWeights::from_query(&query(&[("w_reward", "0"), ("w_availability", "0")])).is_err());
+        assert!(ScoreWeights::from_query(&query(&[("w_reward", "-1"), ("w_uptime", "2")])).is_err());
+        assert!(ScoreWeights::from_query(&query(&[("w_uptime", "lots")])).is_err());
+
+        let template = build(&EligibilityCriteria::default()).eligible_nodes.remove(0);
+        let with = |rate: f64, availability: f64, uptime: Option<u32>| {
+            let mut v = template.clone();
+            v.reward_rates.as_mut().unwrap().combined = rate;
+            v.provider_stats.as_mut().unwrap().availability = Some(availability);
+            v.uptime_signed = uptime;
+            v
+        };
+        let pool = [with(0.002, 50.0, Some(10)), with(0.001, 100.0, Some(40)), with(0.004, 100.0, None)];
+        let pool: Vec<&Validator> = pool.iter().collect();
+
+        let reward_only = ScoreWeights { reward: 1.0, availability: 0.0, uptime: 0.0 };
+        assert_eq!(reward_only.scores(&pool), vec![0.5, 0.25, 1.0]);
+
+        let mixed = ScoreWeights { reward: 0.5, availability: 0.25, uptime: 0.25 };
+        let scores = mixed.scores(&pool);
+        assert!((scores[0] - (0.25 + 0.125 + 0.0625)).abs() < 1e-12);
+        assert!((scores[1] - (0.125 + 0.25 + 0.25)).abs() < 1e-12);
+        // Unknown uptime counts as none
+        assert!((scores[2] - (0.5 + 0.25)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn changes_since_a_snapshot_cover_rates_eligibility_activity_and_membership() {
+        let before = build(&EligibilityCriteria::default());
+        let baseline = Snapshot { timestamp: Utc::now(), entries: snapshot_entries(&before) };
+        let (changed, added, removed) = changes_since(&baseline, &before);
+        assert!(changed.is_empty() && added.is_empty() && removed.is_empty());
+
+        let mut after = before.clone();
+        let demoted = after.eligible_nodes.remove(0);
+        let demoted_id = demoted.id;
+        after.ineligible_nodes.push(demoted);
+        after.eligible_nodes[0].reward_rates.as_mut().unwrap().combined += 0.0001;
+        let rate_id = after.eligible_nodes[0].id;
+        let gone = after.ineligible_nodes.remove(0).id;
+        let mut newcomer = after.ineligible_nodes[0].clone();
+        newcomer.id = 9_999;
+        after.ineligible_nodes.push(newcomer);
+        let flipped = after.ineligible_nodes.iter_mut().find(|v| v.provider_stats.is_some()).unwrap();
+        let flipped_id = flipped.id;
+        let stats = flipped.provider_stats.as_mut().unwrap();
+        stats.active = Some(!stats.active.unwrap_or(false));
+
+        let (changed, added, removed) = changes_since(&baseline, &after);
+        let fields = |id: u32| changed.iter().find(|c| c.validator.id == id).map(|c| c.changed.clone());
+        assert_eq!(fields(rate_id), Some(vec!["combined".to_string()]));
+        assert_eq!(fields(demoted_id), Some(vec!["eligible".to_string()]));
+        assert!(!changed.iter().find(|c| c.validator.id == demoted_id).unwrap().eligible);
+        assert_eq!(fields(flipped_id), Some(vec!["active".to_string()]));
+        assert_eq!(changed.len(), 3);
+        assert_eq!(added, vec![9_999]);
+        assert_eq!(removed, vec![gone]);
+    }
 }